@@ -1,24 +1,43 @@
-use crate::panels;
-use crate::state::{ChatSettings, ConnectionCache, Panels, UiCache};
-use crate::utils::load_accounts;
+use crate::desktop::{DesktopNotification, DesktopNotifier};
+use crate::fonts::FontLoader;
+use crate::keys::{Action, Keybindings};
+use crate::media::MediaCache;
+use crate::panels::{self, PaletteState};
+use crate::state::{
+    account_key, find_account, AccountPrefs, Capabilities, ChannelCommand, ChannelPane,
+    ChatSettings, ConnectionCache, Notification, NotificationKind, NotifyLevel, PaneState, Panels,
+    PopoutWindow, SessionState, SplitDirection, UiCache,
+};
+use crate::theme::{Theme, ThemeSet};
+use crate::utils::{
+    load_account_prefs, load_accounts, load_session, load_settings, message_text,
+    save_account_prefs, save_session, save_settings,
+};
+use chrono::{DateTime, Utc};
 use eframe::egui;
 use oshatori::{
-    client::StateClient,
+    client::{ChannelState, ConnectionState, ConnectionStatus, StateClient},
     connection::{Connection, ConnectionEvent, MockConnection, SockchatConnection},
-    AuthField, Profile, Protocol,
+    AuthField, ChannelType, Message, MessageStatus, MessageType, Profile, Protocol,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex as TokioMutex;
 
+const SESSION_SAVE_DELAY: Duration = Duration::from_secs(1);
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_secs(1);
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NOTIFICATIONS: usize = 200;
+
 pub type DynConnection = Arc<TokioMutex<Box<dyn Connection>>>;
 
 pub struct ChatClient {
     pub state_client: Arc<StateClient>,
     pub cache: Arc<Mutex<UiCache>>,
     pub runtime: Arc<Runtime>,
+    pub media_cache: Arc<MediaCache>,
     pub connections: Arc<Mutex<HashMap<String, DynConnection>>>,
     pub account_to_conn: Arc<Mutex<HashMap<usize, String>>>,
 
@@ -27,22 +46,56 @@ pub struct ChatClient {
     pub temp_auth: Vec<AuthField>,
     pub editing_account: Option<usize>,
     pub temp_profile: Profile,
+    pub temp_prefs: AccountPrefs,
     pub selected_account: Option<usize>,
     pub selected_protocol: Option<usize>,
     pub protocols: Vec<Protocol>,
     pub panels: Panels,
     pub chat_settings: ChatSettings,
     pub show_asset_picker: bool,
+    pub hidden_messages: HashSet<String>,
+    pub editing_message: Option<Message>,
+    pub replying_to: Option<Message>,
+    pub scroll_to_message: Option<String>,
+    pub highlighted_message: Option<(String, Instant)>,
+    pub profile_user: Option<String>,
+    pub themes: ThemeSet,
+    applied_theme: Option<Theme>,
+    pub fonts: FontLoader,
+    pub keybindings: Keybindings,
+    pub palette: Option<PaletteState>,
+    pub switcher: Option<PaletteState>,
+    pub search: Option<String>,
+    pub session: SessionState,
+    saved_session: SessionState,
+    session_saved_at: Instant,
+    settings_changed_at: Option<Instant>,
+    pending_restore: Option<(String, Option<String>)>,
+    pending_selection: Option<(String, String, Instant)>,
+    pub show_notifications: bool,
+    last_notification: u64,
+    window_title: String,
+    desktop: Option<DesktopNotifier>,
+    pub pane_states: HashMap<egui::Id, PaneState>,
+    pub show_channel_popup: bool,
+    pub show_channel_browser: bool,
+    pub channel_name: String,
+    pub channel_password: String,
+    pub user_filter: String,
+    pub message_source: Option<Message>,
 
     pub update_interval: Duration,
 }
 
 impl ChatClient {
-    pub fn new(runtime: Arc<Runtime>) -> Self {
+    pub fn new(runtime: Arc<Runtime>, media_cache: Arc<MediaCache>, ctx: &egui::Context) -> Self {
+        let session = load_session();
+        let fonts = FontLoader::start(&runtime, ctx);
         let client = Self {
             state_client: Arc::new(StateClient::new()),
             cache: Arc::new(Mutex::new(UiCache::default())),
             runtime,
+            media_cache,
             connections: Arc::new(Mutex::new(HashMap::new())),
             account_to_conn: Arc::new(Mutex::new(HashMap::new())),
             new_message: String::new(),
@@ -50,26 +103,57 @@ impl ChatClient {
             temp_auth: Vec::new(),
             editing_account: None,
             temp_profile: Profile::default(),
+            temp_prefs: AccountPrefs::default(),
             selected_account: None,
             selected_protocol: None,
             protocols: available_protocols(),
-            panels: Panels {
-                accounts: true,
-                channels: true,
-                users: true,
-                chat: true,
-                input: true,
-                settings: false,
-            },
-            chat_settings: ChatSettings::default(),
+            panels: session.panels.clone(),
+            chat_settings: load_settings(),
             show_asset_picker: false,
+            hidden_messages: HashSet::new(),
+            editing_message: None,
+            replying_to: None,
+            scroll_to_message: None,
+            highlighted_message: None,
+            profile_user: None,
+            themes: ThemeSet::new(),
+            applied_theme: None,
+            fonts,
+            keybindings: Keybindings::load(),
+            palette: None,
+            switcher: None,
+            search: None,
+            pending_restore: session
+                .active_account
+                .clone()
+                .map(|account| (account, session.active_channel.clone())),
+            pending_selection: None,
+            session: session.clone(),
+            saved_session: session,
+            show_notifications: false,
+            last_notification: 0,
+            window_title: Self::name().to_string(),
+            desktop: None,
+            session_saved_at: Instant::now(),
+            settings_changed_at: None,
+            pane_states: HashMap::new(),
+            show_channel_popup: false,
+            show_channel_browser: false,
+            channel_name: String::new(),
+            channel_password: String::new(),
+            user_filter: String::new(),
+            message_source: None,
             update_interval: Duration::from_millis(500),
         };
 
         {
             let mut cache = client.cache.lock().unwrap();
             cache.accounts = load_accounts();
+            cache.account_prefs = load_account_prefs(cache.accounts.len());
         }
+        client
+            .media_cache
+            .set_limits(client.chat_settings.media_limits());
 
         client.start_updates();
         client.auto_connect_accounts();
@@ -102,7 +186,13 @@ impl ChatClient {
                     let account_index = conn_to_acc.get(conn_id).copied().unwrap_or(0);
 
                     if let Some(state) = state_client.get_connection(conn_id).await {
-                        let channels: Vec<String> = state.channels.keys().cloned().collect();
+                        let mut channels: Vec<String> = state.channels.keys().cloned().collect();
+                        channels.sort();
+                        let channel_users: HashMap<String, Vec<String>> = state
+                            .channels
+                            .iter()
+                            .map(|(id, ch)| (id.clone(), ch.users.keys().cloned().collect()))
+                            .collect();
                         let mut current_channel = None;
                         let mut assets = HashMap::new();
                         let mut global_users = HashMap::new();
@@ -130,17 +220,99 @@ impl ChatClient {
                              None
                         };
 
+                        let (prefs, mut read_until, mut notified_until, watched) = {
+                            let c = cache.lock().unwrap();
+                            (
+                                c.account_prefs
+                                    .get(account_index)
+                                    .cloned()
+                                    .unwrap_or_default(),
+                                c.connections
+                                    .get(conn_id)
+                                    .map(|e| e.read_until.clone())
+                                    .unwrap_or_default(),
+                                c.connections
+                                    .get(conn_id)
+                                    .map(|e| e.notified_until.clone())
+                                    .unwrap_or_default(),
+                                c.watched_channels
+                                    .iter()
+                                    .filter(|(index, _)| *index == account_index)
+                                    .map(|(_, id)| id.clone())
+                                    .collect::<HashSet<String>>(),
+                            )
+                        };
+                        let channel_states: HashMap<String, ChannelState> = watched
+                            .iter()
+                            .filter_map(|id| Some((id.clone(), state.channels.get(id)?.clone())))
+                            .collect();
+                        let own_names = own_names(current_user.as_ref());
+                        let new_notifications = collect_notifications(
+                            &state,
+                            account_index,
+                            &prefs,
+                            current_user.as_ref(),
+                            &watched,
+                            &mut notified_until,
+                        );
+                        let mut unread = HashMap::new();
+                        let mut highlights = HashMap::new();
+                        let mut last_activity = HashMap::new();
+
+                        for (id, ch) in &state.channels {
+                            let latest = ch.messages.iter().map(|m| m.timestamp).max();
+                            if let Some(latest) = latest {
+                                last_activity.insert(id.clone(), latest);
+                            }
+                            let since = read_until
+                                .entry(id.clone())
+                                .or_insert_with(|| latest.unwrap_or_else(Utc::now));
+                            if state.current_channel.as_ref() == Some(id) || watched.contains(id) {
+                                if let Some(latest) = latest {
+                                    *since = latest;
+                                }
+                                continue;
+                            }
+                            if prefs.is_muted(id) {
+                                continue;
+                            }
+
+                            for msg in ch.messages.iter().filter(|m| {
+                                m.timestamp > *since
+                                    && m.message_type == MessageType::Normal
+                                    && !m.sender_id.as_ref().is_some_and(|s| {
+                                        let profile = ch.users.get(s).or(state.global_users.get(s));
+                                        prefs.is_ignored(s, profile)
+                                    })
+                            }) {
+                                *unread.entry(id.clone()).or_insert(0) += 1;
+                                let text =
+                                    message_text(&msg.content, &state.global_assets).to_lowercase();
+                                if mentions(&text, &own_names) {
+                                    *highlights.entry(id.clone()).or_insert(0) += 1;
+                                }
+                            }
+                        }
+
                         let conn_cache = ConnectionCache {
                             connection_id: conn_id.clone(),
                             account_index,
                             status: state.status.clone(),
                             channels,
+                            channel_users,
                             current_channel,
                             assets,
                             global_users,
                             current_user,
-                            pending_messages: Vec::new(),
-                            last_ping: None,
+                            pending_messages: HashMap::new(),
+                            tombstones: HashMap::new(),
+                            read_until,
+                            unread,
+                            highlights,
+                            last_activity,
+                            channel_states,
+                            notified_until,
+                            new_notifications,
                         };
 
                         connection_caches.insert(conn_id.clone(), conn_cache);
@@ -150,19 +322,67 @@ impl ChatClient {
                 if let Ok(mut c) = cache.lock() {
                     let active = c.active_connection.clone();
 
-                    for (conn_id, new_cache) in connection_caches {
+                    for (conn_id, mut new_cache) in connection_caches {
+                        for mut notification in std::mem::take(&mut new_cache.new_notifications) {
+                            c.next_notification += 1;
+                            notification.id = c.next_notification;
+                            c.notifications.push(notification);
+                        }
+
                         if let Some(existing) = c.connections.get_mut(&conn_id) {
-                            if let Some(ref ch) = new_cache.current_channel {
-                                existing.pending_messages.retain(|pending| {
-                                    !ch.messages.iter().any(|m| {
-                                        m.sender_id == pending.sender_id
-                                            && m.content == pending.content
-                                    })
-                                });
+                            for (channel_id, pending) in existing.pending_messages.iter_mut() {
+                                let delivered = new_cache
+                                    .current_channel
+                                    .as_ref()
+                                    .filter(|ch| &ch.channel.id == channel_id)
+                                    .or(new_cache.channel_states.get(channel_id));
+                                if let Some(ch) = delivered {
+                                    pending.retain(|pending| {
+                                        !ch.messages.iter().any(|m| {
+                                            m.sender_id == pending.sender_id
+                                                && m.content == pending.content
+                                        })
+                                    });
+                                }
                             }
+                            existing
+                                .pending_messages
+                                .retain(|_, pending| !pending.is_empty());
 
                             existing.status = new_cache.status;
                             existing.channels = new_cache.channels;
+                            existing.channel_users = new_cache.channel_users;
+                            existing.read_until = new_cache.read_until;
+                            existing.unread = new_cache.unread;
+                            existing.highlights = new_cache.highlights;
+                            existing.last_activity = new_cache.last_activity;
+                            existing.channel_states = new_cache.channel_states;
+                            existing.notified_until = new_cache.notified_until;
+                            if let (Some(old), Some(new)) =
+                                (&existing.current_channel, &new_cache.current_channel)
+                            {
+                                if old.channel.id == new.channel.id {
+                                    let tombstones = existing
+                                        .tombstones
+                                        .entry(old.channel.id.clone())
+                                        .or_default();
+                                    if new.messages.is_empty() {
+                                        tombstones.clear();
+                                    }
+                                    for msg in &old.messages {
+                                        let removed = msg.id.is_some()
+                                            && !new.messages.iter().any(|m| m.id == msg.id)
+                                            && !tombstones.iter().any(|m| m.id == msg.id);
+                                        if removed && !new.messages.is_empty() {
+                                            tombstones.push(Message {
+                                                status: MessageStatus::Deleted,
+                                                ..msg.clone()
+                                            });
+                                        }
+                                    }
+                                }
+                            }
+
                             existing.current_channel = new_cache.current_channel;
                             existing.assets = new_cache.assets;
                             existing.global_users = new_cache.global_users;
@@ -173,6 +393,8 @@ impl ChatClient {
                     }
 
                     c.connections.retain(|id, _| conn_ids.contains(id));
+                    let overflow = c.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+                    c.notifications.drain(..overflow);
 
                     if active.is_none() && !c.connections.is_empty() {
                         c.active_connection = c.connections.keys().next().cloned();
@@ -193,6 +415,102 @@ impl ChatClient {
             .cloned()
     }
 
+    pub fn account_prefs(&self, account_index: usize) -> AccountPrefs {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .account_prefs
+            .get(account_index)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn update_account_prefs(&self, account_index: usize, f: impl FnOnce(&mut AccountPrefs)) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(prefs) = cache.account_prefs.get_mut(account_index) {
+            f(prefs);
+            save_account_prefs(&cache.account_prefs);
+        }
+    }
+
+    pub fn capabilities(&self, account_index: usize) -> Capabilities {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .accounts
+            .get(account_index)
+            .map(|a| Capabilities::for_protocol(&a.protocol_name))
+            .unwrap_or_default()
+    }
+
+    pub fn find_user(&self, user_id: &str) -> Option<Profile> {
+        let conn = self.active_connection()?;
+        conn.current_channel
+            .as_ref()
+            .and_then(|ch| ch.users.get(user_id))
+            .or_else(|| conn.global_users.get(user_id))
+            .cloned()
+    }
+
+    pub fn connection_for_account(&self, account_index: usize) -> Option<ConnectionCache> {
+        let conn_id = self
+            .account_to_conn
+            .lock()
+            .unwrap()
+            .get(&account_index)
+            .cloned()?;
+        self.cache
+            .lock()
+            .unwrap()
+            .connections
+            .get(&conn_id)
+            .cloned()
+    }
+
+    pub fn account_key(&self, account_index: usize) -> Option<String> {
+        self.cache
+            .lock()
+            .unwrap()
+            .accounts
+            .get(account_index)
+            .map(account_key)
+    }
+
+    pub fn find_account(&self, key: &str) -> Option<usize> {
+        find_account(&self.cache.lock().unwrap().accounts, key)
+    }
+
+    pub fn desktop_error(&self) -> Option<String> {
+        self.desktop.as_ref()?.error()
+    }
+
+    pub fn pop_out(&mut self, account_index: usize, channel_id: String) {
+        let Some(account) = self.account_key(account_index) else {
+            return;
+        };
+        if !self
+            .session
+            .popouts
+            .iter()
+            .any(|p| p.account == account && p.channel_id == channel_id)
+        {
+            self.session
+                .popouts
+                .push(PopoutWindow::new(account, channel_id));
+        }
+    }
+
+    pub fn split_channel(&mut self, account_index: usize, channel_id: String) {
+        let Some(account) = self.account_key(account_index) else {
+            return;
+        };
+        let pane = ChannelPane {
+            account,
+            channel_id,
+        };
+        if !self.session.panes.contains(&pane) {
+            self.session.panes.push(pane);
+        }
+    }
+
     pub fn set_active_connection(&self, conn_id: String) {
         let mut cache = self.cache.lock().unwrap();
         if cache.connections.contains_key(&conn_id) {
@@ -200,6 +518,98 @@ impl ChatClient {
         }
     }
 
+    pub fn run_action(&mut self, ctx: &egui::Context, action: Action) {
+        match action {
+            Action::PreviousChannel => self.step_channel(-1),
+            Action::NextChannel => self.step_channel(1),
+            Action::NextUnread => self.next_unread(),
+            Action::NextConnection => self.next_connection(),
+            Action::FocusComposer => {
+                ctx.memory_mut(|m| m.request_focus(egui::Id::new("composer")));
+            }
+            Action::ToggleAccounts => self.panels.accounts = !self.panels.accounts,
+            Action::ToggleChannels => self.panels.channels = !self.panels.channels,
+            Action::ToggleUsers => self.panels.users = !self.panels.users,
+            Action::ToggleChat => self.panels.chat = !self.panels.chat,
+            Action::ToggleInput => self.panels.input = !self.panels.input,
+            Action::ToggleSettings => self.panels.settings = !self.panels.settings,
+            Action::OpenSearch => {
+                self.search.get_or_insert_with(String::new);
+                ctx.memory_mut(|m| m.request_focus(egui::Id::new("search")));
+            }
+            Action::MarkAllRead => self.mark_all_read(),
+            Action::CommandPalette => self.palette = Some(PaletteState::default()),
+            Action::QuickSwitcher => self.switcher = Some(PaletteState::default()),
+        }
+    }
+
+    fn sorted_channels(&self, conn: &ConnectionCache) -> Vec<String> {
+        let mut channels = conn.channels.clone();
+        self.account_prefs(conn.account_index)
+            .sort_channels(&mut channels);
+        channels
+    }
+
+    fn step_channel(&self, delta: isize) {
+        let Some(conn) = self.active_connection() else {
+            return;
+        };
+        let channels = self.sorted_channels(&conn);
+        if channels.is_empty() {
+            return;
+        }
+        let current = conn
+            .current_channel
+            .as_ref()
+            .and_then(|ch| channels.iter().position(|id| id == &ch.channel.id));
+        let next = match current {
+            Some(i) => (i as isize + delta).rem_euclid(channels.len() as isize) as usize,
+            None => 0,
+        };
+        self.sync_selection(channels[next].clone());
+    }
+
+    fn next_unread(&self) {
+        let Some(conn) = self.active_connection() else {
+            return;
+        };
+        if let Some(channel_id) = self
+            .sorted_channels(&conn)
+            .into_iter()
+            .find(|id| conn.unread.get(id).is_some_and(|n| *n > 0))
+        {
+            self.sync_selection(channel_id);
+        }
+    }
+
+    fn next_connection(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        let mut ids: Vec<String> = cache.connections.keys().cloned().collect();
+        if ids.is_empty() {
+            return;
+        }
+        ids.sort();
+        let next = cache
+            .active_connection
+            .as_ref()
+            .and_then(|id| ids.iter().position(|i| i == id))
+            .map(|i| (i + 1) % ids.len())
+            .unwrap_or(0);
+        cache.active_connection = Some(ids[next].clone());
+    }
+
+    pub fn mark_all_read(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        let now = Utc::now();
+        for conn in cache.connections.values_mut() {
+            for channel_id in &conn.channels {
+                conn.read_until.insert(channel_id.clone(), now);
+            }
+            conn.unread.clear();
+            conn.highlights.clear();
+        }
+    }
+
     pub fn auto_connect_accounts(&self) {
         let accounts: Vec<(usize, oshatori::Account)> = {
             let cache = self.cache.lock().unwrap();
@@ -248,6 +658,7 @@ impl ChatClient {
                     account_index: account_idx,
                     status: oshatori::client::ConnectionStatus::Connecting,
                     channels: Vec::new(),
+                    channel_users: HashMap::new(),
                     current_channel: None,
                     assets: std::collections::HashMap::new(),
                     global_users: std::collections::HashMap::new(),
@@ -258,8 +669,15 @@ impl ChatClient {
                         color: None,
                         picture: None,
                     }),
-                    pending_messages: Vec::new(),
-                    last_ping: None,
+                    pending_messages: HashMap::new(),
+                    tombstones: HashMap::new(),
+                    read_until: HashMap::new(),
+                    unread: HashMap::new(),
+                    highlights: HashMap::new(),
+                    last_activity: HashMap::new(),
+                    channel_states: HashMap::new(),
+                    notified_until: HashMap::new(),
+                    new_notifications: Vec::new(),
                 };
                 cache.connections.insert(conn_id.clone(), conn_cache);
                 if cache.active_connection.is_none() {
@@ -359,33 +777,365 @@ impl ChatClient {
         });
     }
 
+    // Sliders change settings on every frame while they are dragged, so the
+    // file is only written once the settings have stopped changing.
+    pub fn save_settings_later(&mut self) {
+        self.settings_changed_at = Some(Instant::now());
+    }
+
+    // Selects a channel once the connection reports it, rather than before the
+    // server has confirmed that it exists.
+    pub fn select_when_available(&mut self, channel_id: String) {
+        let Some(conn) = self.active_connection() else {
+            return;
+        };
+        if conn.channels.contains(&channel_id) {
+            self.sync_selection(channel_id);
+        } else {
+            self.pending_selection = Some((conn.connection_id, channel_id, Instant::now()));
+        }
+    }
+
+    fn apply_pending_selection(&mut self) {
+        let Some((conn_id, channel_id, since)) = self.pending_selection.clone() else {
+            return;
+        };
+        let (active, known) = {
+            let cache = self.cache.lock().unwrap();
+            (
+                cache.active_connection.as_ref() == Some(&conn_id),
+                cache
+                    .connections
+                    .get(&conn_id)
+                    .is_some_and(|c| c.channels.contains(&channel_id)),
+            )
+        };
+        if !active || since.elapsed() > SELECTION_TIMEOUT {
+            self.pending_selection = None;
+        } else if known {
+            self.sync_selection(channel_id);
+            self.pending_selection = None;
+        }
+    }
+
+    pub fn open_direct_message(&mut self, ctx: &egui::Context, user_id: &str, name: &str) {
+        use oshatori::connection::ChannelEvent;
+        use oshatori::{Channel, ChannelType};
+
+        let Some(conn) = self.active_connection() else {
+            return;
+        };
+        if self.capabilities(conn.account_index).text_commands {
+            // Sockchat has no direct channels; whispers are sent with /msg from
+            // whichever channel is current.
+            self.new_message = format!("/msg {} ", name);
+            ctx.memory_mut(|m| m.request_focus(egui::Id::new("composer")));
+            return;
+        }
+
+        self.send_event(ConnectionEvent::Channel {
+            event: ChannelEvent::New {
+                channel: Channel {
+                    id: user_id.to_string(),
+                    name: Some(name.to_string()),
+                    channel_type: ChannelType::Direct,
+                },
+            },
+        });
+        self.select_when_available(user_id.to_string());
+        ctx.memory_mut(|m| m.request_focus(egui::Id::new("composer")));
+    }
+
+    pub fn run_channel_command(&mut self, command: ChannelCommand) {
+        if let Some(conn) = self.active_connection() {
+            self.run_channel_command_on(&conn, command);
+        }
+    }
+
+    // Split panes and pop-outs run commands against their own connection,
+    // which is not necessarily the active one.
+    pub fn run_channel_command_on(&mut self, conn: &ConnectionCache, command: ChannelCommand) {
+        use oshatori::connection::{ChannelEvent, ChatEvent};
+        use oshatori::{Channel, ChannelType, MessageFragment};
+
+        let conn_id = conn.connection_id.clone();
+        let active = self.cache.lock().unwrap().active_connection.as_ref() == Some(&conn_id);
+        let capabilities = self.capabilities(conn.account_index);
+        let current = conn
+            .current_channel
+            .as_ref()
+            .map(|ch| ch.channel.id.clone());
+
+        if capabilities.text_commands {
+            // The server switches us into the channel itself once the command
+            // succeeds, so there is nothing to select locally.
+            let lines = match &command {
+                ChannelCommand::Join { name, password } => {
+                    vec![format!(
+                        "/join {} {}",
+                        name,
+                        password.as_deref().unwrap_or("")
+                    )]
+                }
+                ChannelCommand::Create { name, password } => {
+                    let mut lines = vec![format!("/create {}", name)];
+                    if let Some(password) = password {
+                        lines.push(format!("/password {}", password));
+                    }
+                    lines
+                }
+                ChannelCommand::Leave(_) => Vec::new(),
+                ChannelCommand::Browse => {
+                    self.show_channel_browser = true;
+                    Vec::new()
+                }
+            };
+            for line in lines {
+                self.send_event_to(
+                    conn_id.clone(),
+                    ConnectionEvent::Chat {
+                        event: ChatEvent::New {
+                            channel_id: current.clone(),
+                            message: Message {
+                                id: None,
+                                sender_id: None,
+                                content: vec![MessageFragment::Text(line.trim().to_string())],
+                                timestamp: chrono::Utc::now(),
+                                message_type: MessageType::CurrentUser,
+                                status: MessageStatus::Sent,
+                            },
+                        },
+                    },
+                );
+            }
+            return;
+        }
+
+        match command {
+            ChannelCommand::Join { name, .. } => {
+                self.send_event_to(
+                    conn_id.clone(),
+                    ConnectionEvent::Channel {
+                        event: ChannelEvent::Join {
+                            channel_id: name.clone(),
+                        },
+                    },
+                );
+                if active {
+                    self.select_when_available(name);
+                }
+            }
+            ChannelCommand::Create { name, .. } => {
+                self.send_event_to(
+                    conn_id.clone(),
+                    ConnectionEvent::Channel {
+                        event: ChannelEvent::New {
+                            channel: Channel {
+                                id: name.clone(),
+                                name: Some(name.clone()),
+                                channel_type: ChannelType::Group,
+                            },
+                        },
+                    },
+                );
+                if active {
+                    self.select_when_available(name);
+                }
+            }
+            ChannelCommand::Leave(name) => {
+                if !capabilities.leave_channels {
+                    return;
+                }
+                let Some(channel_id) = name.or(current) else {
+                    return;
+                };
+                self.send_event_to(
+                    conn_id.clone(),
+                    ConnectionEvent::Channel {
+                        event: ChannelEvent::Leave {
+                            channel_id: channel_id.clone(),
+                        },
+                    },
+                );
+                self.send_event_to(
+                    conn_id,
+                    ConnectionEvent::Channel {
+                        event: ChannelEvent::Remove { channel_id },
+                    },
+                );
+            }
+            ChannelCommand::Browse => self.show_channel_browser = true,
+        }
+    }
+
     pub fn send_event(&self, event: ConnectionEvent) {
         let active = {
             let cache = self.cache.lock().unwrap();
             cache.active_connection.clone()
         };
+        if let Some(cid) = active {
+            self.send_event_to(cid, event);
+        }
+    }
+
+    pub fn send_event_to(&self, conn_id: String, event: ConnectionEvent) {
         let connections = self.connections.clone();
 
         self.runtime.spawn(async move {
-            if let Some(cid) = active {
-                let conn = {
-                    let conns = connections.lock().unwrap();
-                    conns.get(&cid).cloned()
-                };
-                if let Some(conn) = conn {
-                    let mut conn = conn.lock().await;
-                    let _ = conn.send(event).await;
-                }
+            let conn = {
+                let conns = connections.lock().unwrap();
+                conns.get(&conn_id).cloned()
+            };
+            if let Some(conn) = conn {
+                let mut conn = conn.lock().await;
+                let _ = conn.send(event).await;
             }
         });
     }
 
-    pub fn add_pending_message(&self, message: oshatori::Message) {
+    pub fn add_pending_message(&self, conn_id: &str, channel_id: &str, message: Message) {
         let mut cache = self.cache.lock().unwrap();
-        let conn_id = cache.active_connection.clone();
-        if let Some(conn_id) = conn_id {
-            if let Some(conn_cache) = cache.connections.get_mut(&conn_id) {
-                conn_cache.pending_messages.push(message);
+        if let Some(conn_cache) = cache.connections.get_mut(conn_id) {
+            conn_cache
+                .pending_messages
+                .entry(channel_id.to_string())
+                .or_default()
+                .push(message);
+        }
+    }
+
+    fn restore_selection(&mut self) {
+        let Some((account, channel_id)) = self.pending_restore.clone() else {
+            return;
+        };
+        let Some(account_index) = self.find_account(&account) else {
+            self.pending_restore = None;
+            return;
+        };
+        let Some(conn) = self.connection_for_account(account_index) else {
+            return;
+        };
+        if conn.status != ConnectionStatus::Connected || conn.channels.is_empty() {
+            return;
+        }
+
+        self.set_active_connection(conn.connection_id.clone());
+        if let Some(channel_id) = channel_id.filter(|id| conn.channels.contains(id)) {
+            self.sync_selection(channel_id);
+        }
+        self.pending_restore = None;
+    }
+
+    fn notify(&mut self, ctx: &egui::Context) {
+        let focused = ctx.input(|i| i.viewport().focused.unwrap_or(true));
+        let (fresh, unread) = {
+            let mut cache = self.cache.lock().unwrap();
+            let viewing: Option<(String, String)> =
+                cache.active_connection.as_ref().and_then(|id| {
+                    let channel = cache.connections.get(id)?.current_channel.as_ref()?;
+                    Some((id.clone(), channel.channel.id.clone()))
+                });
+            let fresh: Vec<Notification> = cache
+                .notifications
+                .iter()
+                .filter(|n| n.id > self.last_notification)
+                .cloned()
+                .collect();
+            self.last_notification = cache.next_notification;
+
+            if focused {
+                for notification in &mut cache.notifications {
+                    if viewing.as_ref().is_some_and(|(conn, channel)| {
+                        &notification.connection_id == conn && &notification.channel_id == channel
+                    }) {
+                        notification.read = true;
+                    }
+                }
+            }
+            let unread: usize = cache
+                .connections
+                .values()
+                .flat_map(|c| c.unread.values())
+                .sum();
+            (fresh, unread)
+        };
+
+        if !fresh.is_empty() && !focused {
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+            if self.chat_settings.desktop_notifications {
+                let desktop = self
+                    .desktop
+                    .get_or_insert_with(|| DesktopNotifier::start(&self.runtime, ctx));
+                for notification in &fresh {
+                    let image = notification
+                        .sender_picture
+                        .as_ref()
+                        .and_then(|uri| self.media_cache.cached_path(uri));
+                    desktop.show(DesktopNotification::new(
+                        notification,
+                        self.chat_settings.notification_privacy,
+                        image,
+                    ));
+                }
+            }
+        }
+
+        if let Some((connection_id, channel_id)) = self.desktop.as_ref().and_then(|d| d.clicked()) {
+            // The connection may have closed since the notification was shown.
+            let exists = self
+                .cache
+                .lock()
+                .unwrap()
+                .connections
+                .contains_key(&connection_id);
+            if exists {
+                self.set_active_connection(connection_id);
+                self.sync_selection(channel_id);
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+
+        let title = if !focused && unread > 0 {
+            format!("({}) {}", unread, Self::name())
+        } else {
+            Self::name().to_string()
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn record_session(&mut self, ctx: &egui::Context) {
+        ctx.input(|i| {
+            let viewport = i.viewport();
+            self.session.maximized = viewport.maximized.unwrap_or(false);
+            if !self.session.maximized {
+                if let Some(rect) = viewport.inner_rect {
+                    self.session.window_size = Some(rect.size().into());
+                }
+                if let Some(rect) = viewport.outer_rect {
+                    self.session.window_position = Some(rect.min.into());
+                }
+            }
+        });
+        self.session.panels = self.panels.clone();
+
+        if self.pending_restore.is_none() {
+            let cache = self.cache.lock().unwrap();
+            if let Some(conn) = cache
+                .active_connection
+                .as_ref()
+                .and_then(|id| cache.connections.get(id))
+            {
+                self.session.active_account =
+                    cache.accounts.get(conn.account_index).map(account_key);
+                self.session.active_channel = conn
+                    .current_channel
+                    .as_ref()
+                    .map(|ch| ch.channel.id.clone());
             }
         }
     }
@@ -400,8 +1150,51 @@ impl ChatClient {
                     ui.checkbox(&mut self.panels.chat, "Chat");
                     ui.checkbox(&mut self.panels.input, "Input");
                     ui.checkbox(&mut self.panels.settings, "Settings");
+                    ui.separator();
+                    ui.radio_value(
+                        &mut self.session.split,
+                        SplitDirection::Horizontal,
+                        "Split side by side",
+                    );
+                    ui.radio_value(
+                        &mut self.session.split,
+                        SplitDirection::Vertical,
+                        "Split stacked",
+                    );
+                    if ui
+                        .add_enabled(
+                            !self.session.panes.is_empty(),
+                            egui::Button::new("Close split panes"),
+                        )
+                        .clicked()
+                    {
+                        self.session.panes.clear();
+                        ui.close_menu();
+                    }
                 });
 
+                ui.separator();
+                let unread = self
+                    .cache
+                    .lock()
+                    .unwrap()
+                    .notifications
+                    .iter()
+                    .filter(|n| !n.read)
+                    .count();
+                let bell = if unread > 0 {
+                    format!("🔔 {}", unread)
+                } else {
+                    "🔔".to_string()
+                };
+                if ui
+                    .selectable_label(self.show_notifications, bell)
+                    .on_hover_text("Notifications")
+                    .clicked()
+                {
+                    self.show_notifications = !self.show_notifications;
+                }
+
                 ui.separator();
                 ui.label("Taitsu");
             });
@@ -411,6 +1204,45 @@ impl ChatClient {
 
 impl eframe::App for ChatClient {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        for (action, shortcut) in self.keybindings.bindings.clone() {
+            if ctx.input_mut(|i| i.consume_shortcut(&shortcut)) {
+                self.run_action(ctx, action);
+            }
+        }
+
+        self.themes.poll();
+        let theme = self.themes.get(&self.chat_settings.theme);
+        if self.applied_theme.as_ref() != Some(&theme) {
+            theme.apply(ctx);
+            self.applied_theme = Some(theme);
+        }
+        self.fonts
+            .poll(&self.runtime, ctx, &self.chat_settings.fonts);
+        if ctx.zoom_factor() != self.chat_settings.ui_scale {
+            ctx.set_zoom_factor(self.chat_settings.ui_scale);
+        }
+
+        let watched = self
+            .session
+            .popouts
+            .iter()
+            .map(|p| (&p.account, &p.channel_id))
+            .chain(
+                self.session
+                    .panes
+                    .iter()
+                    .map(|p| (&p.account, &p.channel_id)),
+            )
+            .filter_map(|(account, channel_id)| {
+                Some((self.find_account(account)?, channel_id.clone()))
+            })
+            .collect();
+        self.cache.lock().unwrap().watched_channels = watched;
+        self.restore_selection();
+        self.apply_pending_selection();
+        self.record_session(ctx);
+        self.notify(ctx);
+
         self.menu_bar(ctx);
         panels::draw_accounts(self, ctx);
         panels::draw_channels(self, ctx);
@@ -418,8 +1250,136 @@ impl eframe::App for ChatClient {
         panels::draw_settings(self, ctx);
         panels::draw_chat(self, ctx);
         panels::draw_popups(self, ctx);
+        panels::draw_notifications(self, ctx);
+        panels::draw_palette(self, ctx);
+        panels::draw_switcher(self, ctx);
+        panels::draw_popouts(self, ctx);
+
+        if self.session != self.saved_session
+            && self.session_saved_at.elapsed() > SESSION_SAVE_DELAY
+        {
+            save_session(&self.session);
+            self.saved_session = self.session.clone();
+            self.session_saved_at = Instant::now();
+        }
+        if self
+            .settings_changed_at
+            .is_some_and(|changed| changed.elapsed() > SETTINGS_SAVE_DELAY)
+        {
+            save_settings(&self.chat_settings);
+            self.settings_changed_at = None;
+        }
         ctx.request_repaint_after(Duration::from_millis(100));
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        save_session(&self.session);
+        if self.settings_changed_at.is_some() {
+            save_settings(&self.chat_settings);
+        }
+        self.media_cache.flush();
+    }
+}
+
+fn own_names(current_user: Option<&Profile>) -> Vec<String> {
+    current_user
+        .iter()
+        .flat_map(|u| [u.username.clone(), u.display_name.clone()])
+        .flatten()
+        .map(|n| n.to_lowercase())
+        .collect()
+}
+
+// A name only counts when it stands on its own, so "al" doesn't match "also".
+fn mentions(text: &str, own_names: &[String]) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    own_names
+        .iter()
+        .filter(|name| !name.is_empty())
+        .any(|name| {
+            text.match_indices(name.as_str()).any(|(start, _)| {
+                let before = text[..start].chars().next_back();
+                let after = text[start + name.len()..].chars().next();
+                !before.is_some_and(is_word) && !after.is_some_and(is_word)
+            })
+        })
+}
+
+fn collect_notifications(
+    state: &ConnectionState,
+    account_index: usize,
+    prefs: &AccountPrefs,
+    current_user: Option<&Profile>,
+    watched: &HashSet<String>,
+    notified_until: &mut HashMap<String, DateTime<Utc>>,
+) -> Vec<Notification> {
+    let own_id = current_user.and_then(|u| u.id.as_ref());
+    let own_names = own_names(current_user);
+    let mut notifications = Vec::new();
+
+    for (id, ch) in &state.channels {
+        let latest = ch.messages.iter().map(|m| m.timestamp).max();
+        let since = *notified_until
+            .entry(id.clone())
+            .or_insert_with(|| latest.unwrap_or_else(Utc::now));
+        if let Some(latest) = latest {
+            notified_until.insert(id.clone(), since.max(latest));
+        }
+
+        let level = prefs.notify_level(id);
+        if prefs.is_muted(id) || level == NotifyLevel::None {
+            continue;
+        }
+        let viewing = state.current_channel.as_ref() == Some(id) || watched.contains(id);
+
+        for msg in ch.messages.iter().filter(|m| {
+            m.timestamp > since
+                && m.message_type == MessageType::Normal
+                && m.sender_id.as_ref() != own_id
+        }) {
+            let profile = msg
+                .sender_id
+                .as_ref()
+                .and_then(|s| ch.users.get(s).or(state.global_users.get(s)));
+            if msg
+                .sender_id
+                .as_ref()
+                .is_some_and(|s| prefs.is_ignored(s, profile))
+            {
+                continue;
+            }
+
+            let text = message_text(&msg.content, &state.global_assets);
+            let kind = if matches!(ch.channel.channel_type, ChannelType::Direct) {
+                NotificationKind::Direct
+            } else if mentions(&text.to_lowercase(), &own_names) {
+                NotificationKind::Mention
+            } else {
+                NotificationKind::Message
+            };
+            if kind == NotificationKind::Message && (level != NotifyLevel::All || viewing) {
+                continue;
+            }
+
+            notifications.push(Notification {
+                id: 0,
+                account_index,
+                connection_id: state.connection_id.clone(),
+                channel_id: id.clone(),
+                sender: msg
+                    .sender_id
+                    .as_ref()
+                    .map(|s| prefs.display_name(s, profile))
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                sender_picture: profile.and_then(|p| p.picture.clone()),
+                text,
+                timestamp: msg.timestamp,
+                kind,
+                read: false,
+            });
+        }
+    }
+    notifications
 }
 
 fn available_protocols() -> Vec<Protocol> {
@@ -428,3 +1388,144 @@ fn available_protocols() -> Vec<Protocol> {
         SockchatConnection::new().protocol_spec(),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn mentions_match_whole_words_only() {
+        let own = names(&["al"]);
+        assert!(mentions("hey al", &own));
+        assert!(mentions("@al, look", &own));
+        assert!(mentions("al: ping", &own));
+        assert!(!mentions("also this", &own));
+        assert!(!mentions("royal", &own));
+        assert!(!mentions("al_bot says hi", &own));
+        assert!(mentions("also, al", &own));
+    }
+
+    #[test]
+    fn mentions_handle_names_with_symbols_and_unicode() {
+        assert!(mentions("thanks j.doe!", &names(&["j.doe"])));
+        assert!(mentions("こんにちは ゆき", &names(&["ゆき"])));
+        assert!(!mentions("ゆきだるま", &names(&["ゆき"])));
+        assert!(!mentions("anything", &names(&[""])));
+    }
+
+    fn message(sender: &str, text: &str, secs: i64) -> Message {
+        Message {
+            id: None,
+            sender_id: Some(sender.to_string()),
+            content: vec![oshatori::MessageFragment::Text(text.to_string())],
+            timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+            message_type: MessageType::Normal,
+            status: MessageStatus::Sent,
+        }
+    }
+
+    fn me() -> Profile {
+        Profile {
+            id: Some("me".to_string()),
+            username: Some("al".to_string()),
+            display_name: None,
+            color: None,
+            picture: None,
+        }
+    }
+
+    // A connection whose channels each already hold an old message, and the
+    // watermarks from a first pass over it, so only later messages notify.
+    fn seen(channels: &[&str]) -> (ConnectionState, HashMap<String, DateTime<Utc>>) {
+        let mut state = ConnectionState::new("conn".to_string(), "Mock".to_string());
+        for id in channels {
+            let mut channel = ChannelState::new(oshatori::Channel {
+                id: id.to_string(),
+                name: None,
+                channel_type: ChannelType::Group,
+            });
+            channel.messages.push(message("bob", "old", 0));
+            state.channels.insert(id.to_string(), channel);
+        }
+        let mut notified_until = HashMap::new();
+        let first = collect(&state, &AccountPrefs::default(), &mut notified_until);
+        assert!(first.is_empty());
+        (state, notified_until)
+    }
+
+    fn post(state: &mut ConnectionState, channel: &str, msg: Message) {
+        state.channels.get_mut(channel).unwrap().messages.push(msg);
+    }
+
+    fn collect(
+        state: &ConnectionState,
+        prefs: &AccountPrefs,
+        notified_until: &mut HashMap<String, DateTime<Utc>>,
+    ) -> Vec<String> {
+        let me = me();
+        let mut texts: Vec<String> =
+            collect_notifications(state, 0, prefs, Some(&me), &HashSet::new(), notified_until)
+                .into_iter()
+                .map(|n| format!("{}: {}", n.channel_id, n.text))
+                .collect();
+        texts.sort();
+        texts
+    }
+
+    #[test]
+    fn notify_levels_filter_plain_messages_and_mentions() {
+        let (mut state, mut until) = seen(&["all", "mentions", "none"]);
+        for channel in ["all", "mentions", "none"] {
+            post(&mut state, channel, message("bob", "hello", 10));
+            post(&mut state, channel, message("bob", "hi al", 11));
+        }
+        let mut prefs = AccountPrefs::default();
+        prefs
+            .channel_notify
+            .insert("all".to_string(), NotifyLevel::All);
+        prefs
+            .channel_notify
+            .insert("none".to_string(), NotifyLevel::None);
+
+        assert_eq!(
+            collect(&state, &prefs, &mut until),
+            names(&["all: hello", "all: hi al", "mentions: hi al"])
+        );
+        // Each message is only reported once.
+        assert!(collect(&state, &prefs, &mut until).is_empty());
+    }
+
+    #[test]
+    fn muted_ignored_and_own_messages_never_notify() {
+        let (mut state, mut until) = seen(&["muted", "open"]);
+        post(&mut state, "muted", message("bob", "hi al", 10));
+        post(&mut state, "open", message("troll", "hi al", 10));
+        post(&mut state, "open", message("me", "hi al", 11));
+        post(&mut state, "open", message("bob", "al?", 12));
+        let prefs = AccountPrefs {
+            muted_channels: names(&["muted"]),
+            ignored_users: names(&["troll"]),
+            ..Default::default()
+        };
+
+        assert_eq!(collect(&state, &prefs, &mut until), names(&["open: al?"]));
+    }
+
+    #[test]
+    fn plain_messages_skip_the_channel_being_viewed() {
+        let (mut state, mut until) = seen(&["here"]);
+        state.current_channel = Some("here".to_string());
+        post(&mut state, "here", message("bob", "hello", 10));
+        post(&mut state, "here", message("bob", "al!", 11));
+        let prefs = AccountPrefs {
+            notify_level: NotifyLevel::All,
+            ..Default::default()
+        };
+
+        assert_eq!(collect(&state, &prefs, &mut until), names(&["here: al!"]));
+    }
+}
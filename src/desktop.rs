@@ -0,0 +1,543 @@
+use crate::state::{Notification, NotificationKind, NotificationPrivacy};
+use eframe::egui;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+pub struct DesktopNotification {
+    pub summary: String,
+    pub body: String,
+    pub image: Option<PathBuf>,
+    pub connection_id: String,
+    pub channel_id: String,
+}
+
+impl DesktopNotification {
+    pub fn new(
+        notification: &Notification,
+        privacy: NotificationPrivacy,
+        image: Option<PathBuf>,
+    ) -> Self {
+        let channel = match notification.channel_id.as_str() {
+            "" => "General",
+            id => id,
+        };
+        let summary = match notification.kind {
+            NotificationKind::Direct => notification.sender.clone(),
+            _ => format!("{} in #{}", notification.sender, channel),
+        };
+        let hidden_body = match notification.kind {
+            NotificationKind::Direct => "Sent you a message",
+            NotificationKind::Mention => "Mentioned you",
+            NotificationKind::Message => "New message",
+        };
+
+        let (summary, body, image) = match privacy {
+            NotificationPrivacy::Full => (summary, notification.text.clone(), image),
+            NotificationPrivacy::HideText => (summary, hidden_body.to_string(), image),
+            NotificationPrivacy::HideAll => {
+                ("Taitsu".to_string(), "New notification".to_string(), None)
+            }
+        };
+        DesktopNotification {
+            summary,
+            body,
+            image,
+            connection_id: notification.connection_id.clone(),
+            channel_id: notification.channel_id.clone(),
+        }
+    }
+}
+
+pub struct DesktopNotifier {
+    outgoing: UnboundedSender<DesktopNotification>,
+    clicked: mpsc::Receiver<(String, String)>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl DesktopNotifier {
+    pub fn start(runtime: &Runtime, ctx: &egui::Context) -> Self {
+        let (outgoing, receiver) = unbounded_channel();
+        let (sender, clicked) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+
+        #[cfg(target_os = "linux")]
+        {
+            let (ctx, failed) = (ctx.clone(), error.clone());
+            runtime.spawn(async move {
+                let result = match zbus::Connection::session().await {
+                    Ok(connection) => dbus::run(connection, receiver, sender, ctx.clone()).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    *failed.lock().unwrap() = Some(err.to_string());
+                    ctx.request_repaint();
+                }
+            });
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (runtime, ctx, receiver, sender);
+            *error.lock().unwrap() = Some("only supported on Linux".to_string());
+        }
+
+        DesktopNotifier {
+            outgoing,
+            clicked,
+            error,
+        }
+    }
+
+    // Why notifications can't be shown, for the settings panel.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    pub fn show(&self, notification: DesktopNotification) {
+        let _ = self.outgoing.send(notification);
+    }
+
+    pub fn clicked(&self) -> Option<(String, String)> {
+        self.clicked.try_recv().ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod dbus {
+    use super::DesktopNotification;
+    use eframe::egui;
+    use futures_util::StreamExt;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use zbus::zvariant::{Structure, Value};
+
+    const IMAGE_SIZE: u32 = 128;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.Notifications",
+        default_service = "org.freedesktop.Notifications",
+        default_path = "/org/freedesktop/Notifications"
+    )]
+    trait Notifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: &str,
+            replaces_id: u32,
+            app_icon: &str,
+            summary: &str,
+            body: &str,
+            actions: &[&str],
+            hints: &HashMap<&str, &Value<'_>>,
+            expire_timeout: i32,
+        ) -> zbus::Result<u32>;
+
+        fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+        #[zbus(signal)]
+        fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+
+        #[zbus(signal)]
+        fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+    }
+
+    // Cached media is stored without an extension, so servers can't tell the
+    // format of an image-path. Send the decoded pixels as image-data instead.
+    fn image_data(path: &Path) -> Option<Structure<'static>> {
+        let image = image::ImageReader::open(path)
+            .ok()?
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()?
+            .thumbnail(IMAGE_SIZE, IMAGE_SIZE)
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+        Some(Structure::from((
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            true,
+            8i32,
+            4i32,
+            image.into_raw(),
+        )))
+    }
+
+    // With body-markup, servers render a subset of HTML in the body, so links
+    // and images in a message would be live and could fetch remote content.
+    fn escape_markup(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    pub async fn run(
+        connection: zbus::Connection,
+        mut outgoing: UnboundedReceiver<DesktopNotification>,
+        clicked: mpsc::Sender<(String, String)>,
+        ctx: egui::Context,
+    ) -> zbus::Result<()> {
+        let proxy = NotificationsProxy::new(&connection).await?;
+        let mut invoked = proxy.receive_action_invoked().await?;
+        let mut closed = proxy.receive_notification_closed().await?;
+        let mut targets: HashMap<u32, (String, String)> = HashMap::new();
+        // Assume markup if the server won't say, since escaping is harmless
+        // next to running it.
+        let markup = proxy
+            .get_capabilities()
+            .await
+            .map(|caps| caps.iter().any(|c| c == "body-markup"))
+            .unwrap_or(true);
+
+        loop {
+            tokio::select! {
+                notification = outgoing.recv() => {
+                    let Some(notification) = notification else {
+                        return Ok(());
+                    };
+                    let image = match notification.image.clone() {
+                        Some(path) => tokio::task::spawn_blocking(move || image_data(&path))
+                            .await
+                            .ok()
+                            .flatten()
+                            .map(Value::from),
+                        None => None,
+                    };
+                    let category = Value::from("im.received");
+                    let mut hints = HashMap::new();
+                    hints.insert("category", &category);
+                    if let Some(image) = &image {
+                        hints.insert("image-data", image);
+                    }
+
+                    let body = if markup {
+                        escape_markup(&notification.body)
+                    } else {
+                        notification.body.clone()
+                    };
+
+                    if let Ok(id) = proxy
+                        .notify(
+                            "Taitsu",
+                            0,
+                            "",
+                            &notification.summary,
+                            &body,
+                            &["default", "Open"],
+                            &hints,
+                            -1,
+                        )
+                        .await
+                    {
+                        targets.insert(id, (notification.connection_id, notification.channel_id));
+                    }
+                }
+                Some(signal) = invoked.next() => {
+                    // Anything on the bus can emit these, so skip malformed ones.
+                    let Ok(args) = signal.args() else {
+                        continue;
+                    };
+                    if let Some(target) = targets.remove(&args.id) {
+                        let _ = clicked.send(target);
+                        ctx.request_repaint();
+                    }
+                }
+                Some(signal) = closed.next() => {
+                    if let Ok(args) = signal.args() {
+                        targets.remove(&args.id);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::desktop::DesktopNotification;
+        use crate::state::{NotificationKind, NotificationPrivacy};
+        use std::io::{BufRead, BufReader};
+        use std::process::{Child, Command, Stdio};
+        use std::time::Duration;
+        use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+        use zbus::object_server::SignalContext;
+        use zbus::zvariant::OwnedValue;
+
+        const PATH: &str = "/org/freedesktop/Notifications";
+
+        struct Bus(Child);
+
+        impl Drop for Bus {
+            fn drop(&mut self) {
+                let _ = self.0.kill();
+                let _ = self.0.wait();
+            }
+        }
+
+        fn private_bus() -> Option<(Bus, String)> {
+            let mut child = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(child.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some((Bus(child), address.trim().to_string()))
+        }
+
+        struct Call {
+            summary: String,
+            body: String,
+            actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+        }
+
+        struct Server {
+            calls: UnboundedSender<Call>,
+        }
+
+        #[zbus::interface(name = "org.freedesktop.Notifications")]
+        impl Server {
+            #[allow(clippy::too_many_arguments)]
+            fn notify(
+                &self,
+                _app_name: String,
+                _replaces_id: u32,
+                _app_icon: String,
+                summary: String,
+                body: String,
+                actions: Vec<String>,
+                hints: HashMap<String, OwnedValue>,
+                _expire_timeout: i32,
+            ) -> u32 {
+                let _ = self.calls.send(Call {
+                    summary,
+                    body,
+                    actions,
+                    hints,
+                });
+                7
+            }
+
+            fn get_capabilities(&self) -> Vec<String> {
+                vec!["body".to_string(), "body-markup".to_string()]
+            }
+
+            #[zbus(signal)]
+            async fn action_invoked(
+                ctxt: &SignalContext<'_>,
+                id: u32,
+                action_key: &str,
+            ) -> zbus::Result<()>;
+        }
+
+        fn notification(privacy: NotificationPrivacy, image: &Path) -> DesktopNotification {
+            DesktopNotification::new(
+                &crate::desktop::tests::notification(NotificationKind::Mention),
+                privacy,
+                Some(image.to_path_buf()),
+            )
+        }
+
+        #[test]
+        fn escape_markup_neutralises_tags_and_entities() {
+            assert_eq!(
+                escape_markup(r#"<img src="https://example.com/x.png"> &amp; <b>hi</b>"#),
+                r#"&lt;img src="https://example.com/x.png"&gt; &amp;amp; &lt;b&gt;hi&lt;/b&gt;"#
+            );
+            assert_eq!(escape_markup("plain text"), "plain text");
+        }
+
+        #[tokio::test]
+        #[ignore = "needs dbus-daemon on PATH"]
+        async fn notifies_over_dbus_and_reports_clicks() {
+            let (_bus, address) = private_bus().expect("dbus-daemon is not available");
+
+            let (calls, mut received) = unbounded_channel();
+            let server = zbus::connection::Builder::address(address.as_str())
+                .unwrap()
+                .name("org.freedesktop.Notifications")
+                .unwrap()
+                .serve_at(PATH, Server { calls })
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let client = zbus::connection::Builder::address(address.as_str())
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+
+            // Cached blobs have no extension.
+            let image = std::env::temp_dir().join(format!("taitsu-avatar-{}", std::process::id()));
+            image::RgbaImage::from_pixel(256, 256, image::Rgba([255, 0, 0, 255]))
+                .save_with_format(&image, image::ImageFormat::Png)
+                .unwrap();
+
+            let (outgoing, receiver) = unbounded_channel();
+            let (sender, clicked) = mpsc::channel();
+            let task = tokio::spawn(run(client, receiver, sender, egui::Context::default()));
+
+            outgoing
+                .send(notification(NotificationPrivacy::Full, &image))
+                .unwrap();
+            let call = received.recv().await.unwrap();
+            assert_eq!(call.summary, "alice in #general");
+            assert_eq!(call.body, "hey @bob");
+            assert_eq!(call.actions, ["default", "Open"]);
+            assert_eq!(
+                String::try_from(call.hints["category"].try_clone().unwrap()).unwrap(),
+                "im.received"
+            );
+            let (width, height, rowstride, alpha, bits, channels, data): (
+                i32,
+                i32,
+                i32,
+                bool,
+                i32,
+                i32,
+                Vec<u8>,
+            ) = call.hints["image-data"]
+                .try_clone()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!((width, height, rowstride), (128, 128, 512));
+            assert_eq!((alpha, bits, channels), (true, 8, 4));
+            assert_eq!(data.len(), 128 * 512);
+            assert!(!call.hints.contains_key("image-path"));
+
+            outgoing
+                .send(notification(NotificationPrivacy::HideAll, &image))
+                .unwrap();
+            let call = received.recv().await.unwrap();
+            assert_eq!(call.summary, "Taitsu");
+            assert_eq!(call.body, "New notification");
+            assert!(!call.hints.contains_key("image-data"));
+
+            // The server advertises body-markup, so tags arrive as text.
+            let mut markup = crate::desktop::tests::notification(NotificationKind::Mention);
+            markup.text = r#"<a href="https://example.com">x</a> & <img src="y">"#.to_string();
+            outgoing
+                .send(DesktopNotification::new(
+                    &markup,
+                    NotificationPrivacy::Full,
+                    None,
+                ))
+                .unwrap();
+            let call = received.recv().await.unwrap();
+            assert_eq!(call.summary, "alice in #general");
+            assert_eq!(
+                call.body,
+                r#"&lt;a href="https://example.com"&gt;x&lt;/a&gt; &amp; &lt;img src="y"&gt;"#
+            );
+
+            // A malformed signal must not stop the notifier.
+            server
+                .emit_signal(
+                    None::<zbus::names::BusName>,
+                    PATH,
+                    "org.freedesktop.Notifications",
+                    "ActionInvoked",
+                    &("bogus",),
+                )
+                .await
+                .unwrap();
+            let ctxt = SignalContext::new(&server, PATH).unwrap();
+            Server::action_invoked(&ctxt, 7, "default").await.unwrap();
+
+            let target =
+                tokio::task::spawn_blocking(move || clicked.recv_timeout(Duration::from_secs(5)))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(target, ("conn".to_string(), "general".to_string()));
+            assert!(!task.is_finished());
+
+            drop(outgoing);
+            task.await.unwrap().unwrap();
+            let _ = std::fs::remove_file(image);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub fn notification(kind: NotificationKind) -> Notification {
+        Notification {
+            id: 1,
+            account_index: 0,
+            connection_id: "conn".to_string(),
+            channel_id: "general".to_string(),
+            sender: "alice".to_string(),
+            sender_picture: Some("https://example.com/alice.png".to_string()),
+            text: "hey @bob".to_string(),
+            timestamp: chrono::Utc::now(),
+            kind,
+            read: false,
+        }
+    }
+
+    #[test]
+    fn full_privacy_shows_sender_text_and_image() {
+        let image = Some(PathBuf::from("/tmp/avatar"));
+        let shown = DesktopNotification::new(
+            &notification(NotificationKind::Mention),
+            NotificationPrivacy::Full,
+            image.clone(),
+        );
+        assert_eq!(shown.summary, "alice in #general");
+        assert_eq!(shown.body, "hey @bob");
+        assert_eq!(shown.image, image);
+        assert_eq!(
+            (shown.connection_id.as_str(), shown.channel_id.as_str()),
+            ("conn", "general")
+        );
+    }
+
+    #[test]
+    fn hide_text_keeps_sender_but_not_message() {
+        let image = Some(PathBuf::from("/tmp/avatar"));
+        let shown = DesktopNotification::new(
+            &notification(NotificationKind::Direct),
+            NotificationPrivacy::HideText,
+            image.clone(),
+        );
+        assert_eq!(shown.summary, "alice");
+        assert_eq!(shown.body, "Sent you a message");
+        assert_eq!(shown.image, image);
+
+        let shown = DesktopNotification::new(
+            &notification(NotificationKind::Message),
+            NotificationPrivacy::HideText,
+            None,
+        );
+        assert_eq!(shown.summary, "alice in #general");
+        assert_eq!(shown.body, "New message");
+    }
+
+    #[test]
+    fn hide_all_reveals_nothing_but_still_targets_channel() {
+        let shown = DesktopNotification::new(
+            &notification(NotificationKind::Mention),
+            NotificationPrivacy::HideAll,
+            Some(PathBuf::from("/tmp/avatar")),
+        );
+        assert_eq!(shown.summary, "Taitsu");
+        assert_eq!(shown.body, "New notification");
+        assert_eq!(shown.image, None);
+        assert_eq!(
+            (shown.connection_id.as_str(), shown.channel_id.as_str()),
+            ("conn", "general")
+        );
+    }
+}
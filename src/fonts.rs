@@ -0,0 +1,234 @@
+use crate::state::{ChatSettings, FontSettings};
+use eframe::egui::{self, FontData, FontDefinitions, FontFamily, FontId};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use tokio::runtime::Runtime;
+
+pub const CHAT_FAMILY: &str = "chat";
+
+// Only one font is picked per group: CJK collections are tens of megabytes
+// each, and the first match already covers the script.
+const FALLBACK_GROUPS: [&[&str]; 3] = [
+    &[
+        "notosanscjk",
+        "notosansjp",
+        "notosanssc",
+        "sourcehansans",
+        "droidsansfallback",
+        "wqy-microhei",
+        "yugoth",
+        "msgothic",
+        "hiragino",
+        "pingfang",
+    ],
+    &["notoemoji", "twemoji", "seguiemj"],
+    &["symbola"],
+];
+
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+        PathBuf::from("/Library/Fonts"),
+        PathBuf::from("/System/Library/Fonts"),
+        PathBuf::from("C:\\Windows\\Fonts"),
+    ];
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".fonts"));
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join("Library/Fonts"));
+    }
+    if let Some(data) = dirs::data_local_dir() {
+        dirs.push(data.join("Microsoft/Windows/Fonts"));
+    }
+    dirs
+}
+
+fn scan(dir: &Path, fonts: &mut Vec<PathBuf>, depth: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.is_dir() {
+            if depth < 4 {
+                scan(&path, fonts, depth + 1);
+            }
+        } else if path.extension().is_some_and(|e| {
+            matches!(
+                e.to_string_lossy().to_lowercase().as_str(),
+                "ttf" | "otf" | "ttc"
+            )
+        }) {
+            fonts.push(path);
+        }
+    }
+}
+
+pub fn system_fonts() -> Vec<PathBuf> {
+    let mut fonts = Vec::new();
+    for dir in font_dirs() {
+        scan(&dir, &mut fonts, 0);
+    }
+    fonts.sort_by_key(|p| font_name(p).to_lowercase());
+    fonts.dedup();
+    fonts
+}
+
+pub fn font_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+pub fn detect_fallbacks(fonts: &[PathBuf]) -> Vec<String> {
+    FALLBACK_GROUPS
+        .iter()
+        .filter_map(|hints| {
+            hints.iter().find_map(|hint| {
+                fonts
+                    .iter()
+                    .filter(|p| font_name(p).to_lowercase().replace(' ', "").contains(hint))
+                    .min_by_key(|p| {
+                        let regular = font_name(p).to_lowercase().contains("regular");
+                        let size = std::fs::metadata(p).map(|m| m.len()).unwrap_or(u64::MAX);
+                        (!regular, size)
+                    })
+            })
+        })
+        .map(|p| p.display().to_string())
+        .collect()
+}
+
+fn load(definitions: &mut FontDefinitions, path: &str) -> Option<String> {
+    if definitions.font_data.contains_key(path) {
+        return Some(path.to_string());
+    }
+    let bytes = std::fs::read(path).ok()?;
+    definitions
+        .font_data
+        .insert(path.to_string(), FontData::from_owned(bytes));
+    Some(path.to_string())
+}
+
+fn definitions(fonts: &FontSettings, system: &[PathBuf]) -> FontDefinitions {
+    let mut definitions = FontDefinitions::default();
+    let defaults = definitions.families.clone();
+    let fallbacks: Vec<String> = if fonts.fallbacks.is_empty() {
+        detect_fallbacks(system)
+    } else {
+        fonts.fallbacks.clone()
+    }
+    .iter()
+    .filter_map(|path| load(&mut definitions, path))
+    .collect();
+
+    let mut family = |primary: Option<&String>, base: &FontFamily| {
+        let mut chain: Vec<String> = primary
+            .and_then(|path| load(&mut definitions, path))
+            .into_iter()
+            .collect();
+        chain.extend(defaults.get(base).cloned().unwrap_or_default());
+        chain.extend(fallbacks.iter().cloned());
+        chain
+    };
+
+    let proportional = family(fonts.ui.as_ref(), &FontFamily::Proportional);
+    let monospace = family(fonts.monospace.as_ref(), &FontFamily::Monospace);
+    let chat = family(
+        fonts.chat.as_ref().or(fonts.ui.as_ref()),
+        &FontFamily::Proportional,
+    );
+
+    definitions
+        .families
+        .insert(FontFamily::Proportional, proportional);
+    definitions
+        .families
+        .insert(FontFamily::Monospace, monospace);
+    definitions
+        .families
+        .insert(FontFamily::Name(CHAT_FAMILY.into()), chat);
+    definitions
+}
+
+// The chat family has to exist before the first frame lays out any text in
+// it, so it is bound to egui's built-in fonts until the real ones load.
+pub fn install_defaults(ctx: &egui::Context) {
+    ctx.set_fonts(definitions(&FontSettings::default(), &[]));
+}
+
+// Scanning the font directories and reading font files is far too slow for
+// the UI thread, so both run on the blocking pool and the fonts are swapped
+// in once they are ready.
+pub struct FontLoader {
+    pub system: Vec<PathBuf>,
+    scanning: Option<mpsc::Receiver<Vec<PathBuf>>>,
+    loading: Option<mpsc::Receiver<FontDefinitions>>,
+    applied: Option<FontSettings>,
+}
+
+impl FontLoader {
+    pub fn start(runtime: &Runtime, ctx: &egui::Context) -> Self {
+        install_defaults(ctx);
+
+        let (sender, scanning) = mpsc::channel();
+        runtime.spawn_blocking(move || {
+            let _ = sender.send(system_fonts());
+        });
+        FontLoader {
+            system: Vec::new(),
+            scanning: Some(scanning),
+            loading: None,
+            applied: None,
+        }
+    }
+
+    pub fn poll(&mut self, runtime: &Runtime, ctx: &egui::Context, fonts: &FontSettings) {
+        if let Some(system) = self.scanning.as_ref().and_then(|r| r.try_recv().ok()) {
+            self.system = system;
+            self.scanning = None;
+            // Detected fallbacks depend on the scan, so load again.
+            self.applied = None;
+        }
+        if let Some(receiver) = &self.loading {
+            match receiver.try_recv() {
+                Ok(definitions) => ctx.set_fonts(definitions),
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => {}
+            }
+            self.loading = None;
+        }
+        if self.applied.as_ref() == Some(fonts) {
+            return;
+        }
+
+        let (sender, loading) = mpsc::channel();
+        let (settings, system, ctx) = (fonts.clone(), self.system.clone(), ctx.clone());
+        runtime.spawn_blocking(move || {
+            let _ = sender.send(definitions(&settings, &system));
+            ctx.request_repaint();
+        });
+        self.loading = Some(loading);
+        self.applied = Some(fonts.clone());
+    }
+}
+
+pub fn chat_font(settings: &ChatSettings) -> FontId {
+    FontId::new(
+        settings.chat_font_size,
+        FontFamily::Name(CHAT_FAMILY.into()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_definitions_bind_the_chat_family() {
+        let definitions = definitions(&FontSettings::default(), &[]);
+        let chat = &definitions.families[&FontFamily::Name(CHAT_FAMILY.into())];
+        assert!(!chat.is_empty());
+        assert_eq!(chat, &definitions.families[&FontFamily::Proportional]);
+    }
+}
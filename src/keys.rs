@@ -0,0 +1,184 @@
+use crate::utils::config_path;
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    PreviousChannel,
+    NextChannel,
+    NextUnread,
+    NextConnection,
+    FocusComposer,
+    ToggleAccounts,
+    ToggleChannels,
+    ToggleUsers,
+    ToggleChat,
+    ToggleInput,
+    ToggleSettings,
+    OpenSearch,
+    MarkAllRead,
+    CommandPalette,
+    QuickSwitcher,
+}
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::PreviousChannel,
+        Action::NextChannel,
+        Action::NextUnread,
+        Action::NextConnection,
+        Action::FocusComposer,
+        Action::ToggleAccounts,
+        Action::ToggleChannels,
+        Action::ToggleUsers,
+        Action::ToggleChat,
+        Action::ToggleInput,
+        Action::ToggleSettings,
+        Action::OpenSearch,
+        Action::MarkAllRead,
+        Action::CommandPalette,
+        Action::QuickSwitcher,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::PreviousChannel => "Previous channel",
+            Action::NextChannel => "Next channel",
+            Action::NextUnread => "Next unread channel",
+            Action::NextConnection => "Switch connection",
+            Action::FocusComposer => "Focus message box",
+            Action::ToggleAccounts => "Toggle accounts panel",
+            Action::ToggleChannels => "Toggle channels panel",
+            Action::ToggleUsers => "Toggle users panel",
+            Action::ToggleChat => "Toggle chat panel",
+            Action::ToggleInput => "Toggle input panel",
+            Action::ToggleSettings => "Toggle settings panel",
+            Action::OpenSearch => "Search messages",
+            Action::MarkAllRead => "Mark all as read",
+            Action::CommandPalette => "Command palette",
+            Action::QuickSwitcher => "Quick switcher",
+        }
+    }
+
+    fn default_binding(&self) -> &'static str {
+        match self {
+            Action::PreviousChannel => "Alt+Up",
+            Action::NextChannel => "Alt+Down",
+            Action::NextUnread => "Alt+Shift+Down",
+            Action::NextConnection => "Ctrl+Tab",
+            Action::FocusComposer => "Ctrl+L",
+            Action::ToggleAccounts => "Ctrl+1",
+            Action::ToggleChannels => "Ctrl+2",
+            Action::ToggleUsers => "Ctrl+3",
+            Action::ToggleChat => "Ctrl+4",
+            Action::ToggleInput => "Ctrl+5",
+            Action::ToggleSettings => "Ctrl+6",
+            Action::OpenSearch => "Ctrl+F",
+            Action::MarkAllRead => "Shift+Escape",
+            Action::CommandPalette => "Ctrl+K",
+            Action::QuickSwitcher => "Ctrl+T",
+        }
+    }
+}
+
+pub fn parse_shortcut(text: &str) -> Option<KeyboardShortcut> {
+    let mut modifiers = Modifiers::NONE;
+    let mut key = None;
+    for part in text.split('+').map(|p| p.trim()) {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" | "cmd" | "command" => modifiers = modifiers | Modifiers::COMMAND,
+            "alt" | "option" => modifiers = modifiers | Modifiers::ALT,
+            "shift" => modifiers = modifiers | Modifiers::SHIFT,
+            _ => key = Some(Key::from_name(part)?),
+        }
+    }
+    Some(KeyboardShortcut::new(modifiers, key?))
+}
+
+pub struct Keybindings {
+    pub bindings: Vec<(Action, KeyboardShortcut)>,
+}
+
+impl Keybindings {
+    pub fn load() -> Self {
+        let path = config_path("keybindings.json");
+        let mut names: BTreeMap<Action, String> = Action::ALL
+            .iter()
+            .map(|a| (*a, a.default_binding().to_string()))
+            .collect();
+
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                if let Ok(custom) = serde_json::from_str::<BTreeMap<Action, String>>(&content) {
+                    names.extend(custom);
+                }
+            }
+            Err(_) => {
+                if let Ok(content) = serde_json::to_string_pretty(&names) {
+                    std::fs::write(&path, content).ok();
+                }
+            }
+        }
+
+        let mut bindings: Vec<(Action, KeyboardShortcut)> = names
+            .into_iter()
+            .filter_map(|(action, text)| Some((action, parse_shortcut(&text)?)))
+            .collect();
+        // Shortcuts with more modifiers go first so Alt+Shift+Down isn't
+        // swallowed by Alt+Down.
+        bindings.sort_by_key(|(_, s)| {
+            std::cmp::Reverse(
+                s.modifiers.shift as u8 + s.modifiers.alt as u8 + s.modifiers.command as u8,
+            )
+        });
+        Keybindings { bindings }
+    }
+
+    pub fn shortcut(&self, action: Action) -> Option<&KeyboardShortcut> {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, s)| s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_key() {
+        let shortcut = parse_shortcut("Ctrl+K").unwrap();
+        assert_eq!(shortcut, KeyboardShortcut::new(Modifiers::COMMAND, Key::K));
+
+        let shortcut = parse_shortcut(" alt + Shift + Down ").unwrap();
+        assert_eq!(
+            shortcut,
+            KeyboardShortcut::new(Modifiers::ALT | Modifiers::SHIFT, Key::ArrowDown)
+        );
+
+        assert_eq!(
+            parse_shortcut("Cmd+Option+1"),
+            parse_shortcut("Control+Alt+1")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_or_missing_keys() {
+        assert!(parse_shortcut("Ctrl+Nope").is_none());
+        assert!(parse_shortcut("Ctrl+Shift").is_none());
+        assert!(parse_shortcut("").is_none());
+    }
+
+    #[test]
+    fn every_default_binding_parses() {
+        for action in Action::ALL {
+            assert!(
+                parse_shortcut(action.default_binding()).is_some(),
+                "{:?}",
+                action
+            );
+        }
+    }
+}
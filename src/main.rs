@@ -1,6 +1,12 @@
 mod app;
+mod desktop;
+mod fonts;
+mod keys;
+mod media;
 mod panels;
+mod preview;
 mod state;
+mod theme;
 mod utils;
 
 use std::sync::Arc;
@@ -8,14 +14,31 @@ use tokio::runtime::Runtime;
 
 fn main() -> eframe::Result<()> {
     let runtime = Arc::new(Runtime::new().unwrap());
+    let media_cache = Arc::new(media::MediaCache::new(runtime.clone()));
 
-    let native_options = eframe::NativeOptions::default();
+    let session = utils::load_session();
+    let mut viewport = eframe::egui::ViewportBuilder::default().with_maximized(session.maximized);
+    if let Some(size) = session.window_size {
+        viewport = viewport.with_inner_size(size);
+    }
+    if let Some(position) = session.window_position {
+        viewport = viewport.with_position(position);
+    }
+    let native_options = eframe::NativeOptions {
+        viewport,
+        ..Default::default()
+    };
     eframe::run_native(
         app::ChatClient::name(),
         native_options,
         Box::new(move |cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            Ok(Box::new(app::ChatClient::new(runtime.clone())))
+            cc.egui_ctx.add_bytes_loader(media_cache.clone());
+            Ok(Box::new(app::ChatClient::new(
+                runtime.clone(),
+                media_cache.clone(),
+                &cc.egui_ctx,
+            )))
         }),
     )
 }
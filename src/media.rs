@@ -0,0 +1,736 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use eframe::egui::{
+    self,
+    load::{Bytes, BytesLoadResult, BytesLoader, BytesPoll, LoadError},
+};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+pub const DEFAULT_BUDGET: u64 = 256 * 1024 * 1024;
+const DEFAULT_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+const INDEX_SAVE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct File {
+    bytes: Arc<[u8]>,
+    mime: Option<String>,
+}
+
+type Entry = Poll<Result<File, String>>;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    hash: String,
+    mime: Option<String>,
+    size: u64,
+    expires: Option<DateTime<Utc>>,
+    last_access: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheIndex {
+    budget: u64,
+    entries: HashMap<String, IndexEntry>,
+}
+
+impl Default for CacheIndex {
+    fn default() -> Self {
+        CacheIndex {
+            budget: DEFAULT_BUDGET,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl CacheIndex {
+    fn usage(&self) -> u64 {
+        let mut seen = std::collections::HashSet::new();
+        self.entries
+            .values()
+            .filter(|e| seen.insert(e.hash.as_str()))
+            .map(|e| e.size)
+            .sum()
+    }
+
+    // Drops least recently used entries until the cache fits its budget and
+    // returns the blob hashes that are no longer referenced by any entry.
+    fn evict(&mut self) -> Vec<String> {
+        let mut usage = self.usage();
+        if usage <= self.budget {
+            return Vec::new();
+        }
+
+        let mut references: HashMap<String, usize> = HashMap::new();
+        for entry in self.entries.values() {
+            *references.entry(entry.hash.clone()).or_default() += 1;
+        }
+        let mut oldest: Vec<(String, DateTime<Utc>)> = self
+            .entries
+            .iter()
+            .map(|(uri, e)| (uri.clone(), e.last_access))
+            .collect();
+        oldest.sort_by_key(|(_, last_access)| *last_access);
+
+        let mut removed = Vec::new();
+        for (uri, _) in oldest {
+            if usage <= self.budget {
+                break;
+            }
+            let Some(entry) = self.entries.remove(&uri) else {
+                continue;
+            };
+            let count = references.entry(entry.hash.clone()).or_default();
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                usage = usage.saturating_sub(entry.size);
+                removed.push(entry.hash);
+            }
+        }
+        removed
+    }
+}
+
+struct Disk {
+    dir: PathBuf,
+    index: Mutex<CacheIndex>,
+    saving: Mutex<()>,
+    save_pending: AtomicBool,
+}
+
+impl Disk {
+    fn blob(&self, hash: &str) -> PathBuf {
+        self.dir.join("blobs").join(hash)
+    }
+
+    fn read(&self, uri: &str, fresh_only: bool) -> Option<File> {
+        let (hash, mime) = {
+            let index = self.index.lock().unwrap();
+            let entry = index.entries.get(uri)?;
+            if fresh_only && entry.expires.map(|e| e <= Utc::now()).unwrap_or(false) {
+                return None;
+            }
+            (entry.hash.clone(), entry.mime.clone())
+        };
+        let bytes = std::fs::read(self.blob(&hash)).ok()?;
+        if let Some(entry) = self.index.lock().unwrap().entries.get_mut(uri) {
+            entry.last_access = Utc::now();
+        }
+        Some(File {
+            bytes: bytes.into(),
+            mime,
+        })
+    }
+
+    fn store(&self, uri: &str, file: &File, expires: Option<DateTime<Utc>>) {
+        let hash = Sha1::digest(&file.bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        std::fs::create_dir_all(self.dir.join("blobs")).ok();
+        let path = self.blob(&hash);
+        if !path.exists() && std::fs::write(&path, &file.bytes).is_err() {
+            return;
+        }
+
+        let removed = {
+            let mut index = self.index.lock().unwrap();
+            // The cache may have been cleared since the blob was written.
+            if !path.exists() {
+                return;
+            }
+            index.entries.insert(
+                uri.to_string(),
+                IndexEntry {
+                    hash,
+                    mime: file.mime.clone(),
+                    size: file.bytes.len() as u64,
+                    expires,
+                    last_access: Utc::now(),
+                },
+            );
+            index.evict()
+        };
+        self.remove_blobs(&removed);
+        self.save();
+    }
+
+    // Moves the blobs aside while holding the index lock, so a concurrent
+    // `store` either sees its blob gone or writes into the fresh directory.
+    // Returns the directory to delete once the lock is released.
+    fn clear(&self) -> Option<PathBuf> {
+        let mut index = self.index.lock().unwrap();
+        index.entries.clear();
+        let trash = self.dir.join(format!("blobs-{}", uuid::Uuid::new_v4()));
+        std::fs::rename(self.dir.join("blobs"), &trash)
+            .is_ok()
+            .then_some(trash)
+    }
+
+    fn remove_blobs(&self, hashes: &[String]) {
+        for hash in hashes {
+            std::fs::remove_file(self.blob(hash)).ok();
+        }
+    }
+
+    // Writers take `saving` before snapshotting the index so the last write to
+    // land on disk is always the newest snapshot.
+    fn save(&self) {
+        let _saving = self.saving.lock().unwrap();
+        let content = serde_json::to_string(&*self.index.lock().unwrap());
+        if let Ok(content) = content {
+            std::fs::write(self.dir.join("index.json"), content).ok();
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MediaLimits {
+    pub max_bytes: u64,
+    pub max_dimension: u32,
+}
+
+impl Default for MediaLimits {
+    fn default() -> Self {
+        MediaLimits {
+            max_bytes: 8 * 1024 * 1024,
+            max_dimension: 4096,
+        }
+    }
+}
+
+impl MediaLimits {
+    fn check(&self, uri: &str, bytes: &[u8]) -> Result<(), String> {
+        if bytes.len() as u64 > self.max_bytes {
+            return Err(format!("{uri:?} exceeds the maximum embed size"));
+        }
+        if let Ok((width, height)) = image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(image::ImageError::IoError)
+            .and_then(|r| r.into_dimensions())
+        {
+            if width.max(height) > self.max_dimension {
+                return Err(format!("{uri:?} exceeds the maximum embed dimensions"));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Freshness {
+    store: bool,
+    expires: Option<DateTime<Utc>>,
+}
+
+fn freshness(headers: &reqwest::header::HeaderMap) -> Freshness {
+    let now = Utc::now();
+    let mut freshness = Freshness {
+        store: true,
+        expires: Some(now + ChronoDuration::seconds(DEFAULT_TTL_SECS)),
+    };
+
+    if let Some(expires) = headers
+        .get(reqwest::header::EXPIRES)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+    {
+        freshness.expires = Some(expires.with_timezone(&Utc));
+    }
+
+    if let Some(cache_control) = headers
+        .get(reqwest::header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+    {
+        for directive in cache_control.split(',').map(|d| d.trim().to_lowercase()) {
+            if directive == "no-store" {
+                freshness.store = false;
+            } else if directive == "no-cache" {
+                freshness.expires = Some(now);
+            } else if let Some(secs) = directive
+                .strip_prefix("max-age=")
+                .and_then(|s| s.parse::<i64>().ok())
+            {
+                freshness.expires = Some(now + ChronoDuration::seconds(secs));
+            }
+        }
+    }
+
+    freshness
+}
+
+pub struct MediaCache {
+    runtime: Arc<Runtime>,
+    http: reqwest::Client,
+    disk: Arc<Disk>,
+    memory: Arc<Mutex<HashMap<String, Entry>>>,
+    limits: Arc<Mutex<MediaLimits>>,
+}
+
+impl MediaCache {
+    pub const ID: &'static str = egui::generate_loader_id!(MediaCache);
+
+    pub fn new(runtime: Arc<Runtime>) -> Self {
+        Self::with_dir(runtime, crate::utils::media_cache_dir())
+    }
+
+    pub fn with_dir(runtime: Arc<Runtime>, dir: PathBuf) -> Self {
+        let index = std::fs::read_to_string(dir.join("index.json"))
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+
+        // Blobs moved aside by `clear` may be left over if the app exited
+        // before they were deleted.
+        let stale: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("blobs-"))
+            .map(|e| e.path())
+            .collect();
+        if !stale.is_empty() {
+            runtime.spawn_blocking(move || {
+                for path in stale {
+                    std::fs::remove_dir_all(path).ok();
+                }
+            });
+        }
+
+        MediaCache {
+            runtime,
+            http: reqwest::Client::new(),
+            disk: Arc::new(Disk {
+                dir,
+                index: Mutex::new(index),
+                saving: Mutex::new(()),
+                save_pending: AtomicBool::new(false),
+            }),
+            memory: Arc::new(Mutex::new(HashMap::new())),
+            limits: Arc::new(Mutex::new(MediaLimits::default())),
+        }
+    }
+
+    pub fn set_limits(&self, limits: MediaLimits) {
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.disk.index.lock().unwrap().usage()
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.disk.index.lock().unwrap().budget
+    }
+
+    pub fn set_budget(&self, budget: u64) {
+        let removed = {
+            let mut index = self.disk.index.lock().unwrap();
+            index.budget = budget;
+            index.evict()
+        };
+        let disk = self.disk.clone();
+        self.runtime.spawn_blocking(move || {
+            disk.remove_blobs(&removed);
+            disk.save();
+        });
+    }
+
+    pub fn clear(&self) {
+        let trash = self.disk.clear();
+        self.memory.lock().unwrap().clear();
+        let disk = self.disk.clone();
+        self.runtime.spawn_blocking(move || {
+            if let Some(trash) = trash {
+                std::fs::remove_dir_all(trash).ok();
+            }
+            disk.save();
+        });
+    }
+
+    pub fn flush(&self) {
+        self.disk.save();
+    }
+
+    pub fn cached_path(&self, uri: &str) -> Option<PathBuf> {
+        let path = self
+            .disk
+            .blob(&self.disk.index.lock().unwrap().entries.get(uri)?.hash);
+        path.exists().then_some(path)
+    }
+
+    fn read_cached(&self, uri: &str, fresh_only: bool) -> Option<File> {
+        let file = self.disk.read(uri, fresh_only)?;
+        self.schedule_save();
+        Some(file)
+    }
+
+    // Access times change on every cache hit, so they are written back in
+    // batches rather than rewriting the index per image.
+    fn schedule_save(&self) {
+        if self.disk.save_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let disk = self.disk.clone();
+        self.runtime.spawn(async move {
+            tokio::time::sleep(INDEX_SAVE_DELAY).await;
+            disk.save_pending.store(false, Ordering::SeqCst);
+            tokio::task::spawn_blocking(move || disk.save()).await.ok();
+        });
+    }
+
+    fn fetch(&self, ctx: &egui::Context, uri: String) {
+        let ctx = ctx.clone();
+        let http = self.http.clone();
+        let disk = self.disk.clone();
+        let memory = self.memory.clone();
+        let limits = *self.limits.lock().unwrap();
+
+        self.runtime.spawn(async move {
+            let result = async {
+                let mut response = http
+                    .get(&uri)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| format!("failed to load {uri:?}: {e}"))?;
+                let freshness = freshness(response.headers());
+                let mime = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_owned());
+                if response
+                    .content_length()
+                    .is_some_and(|len| len > limits.max_bytes)
+                {
+                    return Err(format!("{uri:?} exceeds the maximum embed size"));
+                }
+
+                let mut bytes = Vec::new();
+                while let Some(chunk) = response
+                    .chunk()
+                    .await
+                    .map_err(|e| format!("failed to load {uri:?}: {e}"))?
+                {
+                    bytes.extend_from_slice(&chunk);
+                    if bytes.len() as u64 > limits.max_bytes {
+                        return Err(format!("{uri:?} exceeds the maximum embed size"));
+                    }
+                }
+
+                limits.check(&uri, &bytes)?;
+                Ok::<_, String>((
+                    File {
+                        bytes: bytes.into(),
+                        mime,
+                    },
+                    freshness,
+                ))
+            }
+            .await;
+
+            let entry = {
+                let uri = uri.clone();
+                tokio::task::spawn_blocking(move || match result {
+                    Ok((file, freshness)) => {
+                        if freshness.store {
+                            disk.store(&uri, &file, freshness.expires);
+                        }
+                        Ok(file)
+                    }
+                    Err(err) => match disk.read(&uri, false) {
+                        Some(file) => limits.check(&uri, &file.bytes).map(|()| file),
+                        None => Err(err),
+                    },
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+            };
+
+            memory.lock().unwrap().insert(uri, Poll::Ready(entry));
+            ctx.request_repaint();
+        });
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+impl BytesLoader for MediaCache {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn load(&self, ctx: &egui::Context, uri: &str) -> BytesLoadResult {
+        if !uri.starts_with("http://") && !uri.starts_with("https://") {
+            return Err(LoadError::NotSupported);
+        }
+
+        let entry = self.memory.lock().unwrap().get(uri).cloned();
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                // Limits may have been lowered since the file was cached.
+                let entry = match self.read_cached(uri, true) {
+                    Some(file) => {
+                        let limits = *self.limits.lock().unwrap();
+                        Poll::Ready(limits.check(uri, &file.bytes).map(|()| file))
+                    }
+                    None => Poll::Pending,
+                };
+                self.memory
+                    .lock()
+                    .unwrap()
+                    .insert(uri.to_string(), entry.clone());
+                // Only spawn once Pending is in place, otherwise a fetch that
+                // finishes quickly would have its result overwritten.
+                if entry.is_pending() {
+                    self.fetch(ctx, uri.to_string());
+                }
+                entry
+            }
+        };
+
+        match entry {
+            Poll::Ready(Ok(file)) => Ok(BytesPoll::Ready {
+                size: None,
+                bytes: Bytes::Shared(file.bytes),
+                mime: file.mime,
+            }),
+            Poll::Ready(Err(err)) => Err(LoadError::Loading(err)),
+            Poll::Pending => Ok(BytesPoll::Pending { size: None }),
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.memory.lock().unwrap().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.memory.lock().unwrap().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.memory
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| match entry {
+                Poll::Ready(Ok(file)) => file.bytes.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, CACHE_CONTROL, EXPIRES};
+
+    fn headers(pairs: &[(reqwest::header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn entry(hash: &str, size: u64, age_secs: i64) -> IndexEntry {
+        IndexEntry {
+            hash: hash.to_string(),
+            mime: None,
+            size,
+            expires: None,
+            last_access: Utc::now() - ChronoDuration::seconds(age_secs),
+        }
+    }
+
+    fn disk(name: &str) -> Disk {
+        let dir = std::env::temp_dir().join(format!("taitsu-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        Disk {
+            dir,
+            index: Mutex::new(CacheIndex::default()),
+            saving: Mutex::new(()),
+            save_pending: AtomicBool::new(false),
+        }
+    }
+
+    fn file(bytes: &[u8]) -> File {
+        File {
+            bytes: bytes.into(),
+            mime: None,
+        }
+    }
+
+    #[test]
+    fn clear_moves_blobs_aside_before_releasing_the_index() {
+        let disk = disk("clear");
+        disk.store("https://example.com/a", &file(b"first"), None);
+        assert!(disk.read("https://example.com/a", false).is_some());
+
+        let trash = disk.clear().unwrap();
+        assert!(disk.index.lock().unwrap().entries.is_empty());
+        assert!(!disk.dir.join("blobs").exists());
+        assert!(trash.exists());
+
+        disk.store("https://example.com/b", &file(b"second"), None);
+        let read = disk.read("https://example.com/b", false).unwrap();
+        assert_eq!(&*read.bytes, b"second");
+        std::fs::remove_dir_all(&disk.dir).ok();
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn limits_check_size_and_dimensions() {
+        let bytes = png(32, 16);
+        let limits = MediaLimits {
+            max_bytes: 1024 * 1024,
+            max_dimension: 32,
+        };
+        assert!(limits.check("a", &bytes).is_ok());
+        assert!(MediaLimits {
+            max_dimension: 31,
+            ..limits
+        }
+        .check("a", &bytes)
+        .is_err());
+        assert!(MediaLimits {
+            max_bytes: bytes.len() as u64 - 1,
+            ..limits
+        }
+        .check("a", &bytes)
+        .is_err());
+    }
+
+    #[test]
+    fn limits_apply_to_cache_hits() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let dir = disk("limits").dir;
+        let cache = MediaCache::with_dir(runtime, dir.clone());
+        let uri = "https://example.com/big.png";
+        cache.disk.store(uri, &file(&png(64, 64)), None);
+        cache.set_limits(MediaLimits {
+            max_bytes: 1024 * 1024,
+            max_dimension: 32,
+        });
+
+        let ctx = egui::Context::default();
+        assert!(matches!(cache.load(&ctx, uri), Err(LoadError::Loading(_))));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn freshness_defaults_to_ttl() {
+        let freshness = freshness(&HeaderMap::new());
+        assert!(freshness.store);
+        let ttl = freshness.expires.unwrap() - Utc::now();
+        assert!(ttl > ChronoDuration::seconds(DEFAULT_TTL_SECS - 60));
+    }
+
+    #[test]
+    fn freshness_honors_cache_control() {
+        assert!(!freshness(&headers(&[(CACHE_CONTROL, "public, no-store")])).store);
+
+        let no_cache = freshness(&headers(&[(CACHE_CONTROL, "no-cache")]));
+        assert!(no_cache.store);
+        assert!(no_cache.expires.unwrap() <= Utc::now());
+
+        let max_age = freshness(&headers(&[(CACHE_CONTROL, "Max-Age=60")]));
+        let ttl = max_age.expires.unwrap() - Utc::now();
+        assert!(ttl > ChronoDuration::seconds(50) && ttl <= ChronoDuration::seconds(60));
+    }
+
+    #[test]
+    fn freshness_reads_expires_and_prefers_max_age() {
+        let expires = freshness(&headers(&[(EXPIRES, "Wed, 21 Oct 2015 07:28:00 GMT")]));
+        assert_eq!(
+            expires.expires.unwrap(),
+            DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT").unwrap()
+        );
+
+        let both = freshness(&headers(&[
+            (EXPIRES, "Wed, 21 Oct 2015 07:28:00 GMT"),
+            (CACHE_CONTROL, "max-age=60"),
+        ]));
+        assert!(both.expires.unwrap() > Utc::now());
+    }
+
+    #[test]
+    fn format_size_picks_unit() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(256 * 1024 * 1024), "256.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 * 1024), "3072.0 GiB");
+    }
+
+    #[test]
+    fn evict_removes_least_recently_used_first() {
+        let mut index = CacheIndex {
+            budget: 250,
+            entries: HashMap::new(),
+        };
+        index.entries.insert("old".into(), entry("a", 100, 30));
+        index.entries.insert("mid".into(), entry("b", 100, 20));
+        index.entries.insert("new".into(), entry("c", 100, 10));
+
+        assert_eq!(index.evict(), vec!["a".to_string()]);
+        assert_eq!(index.usage(), 200);
+        assert!(index.entries.contains_key("mid") && index.entries.contains_key("new"));
+    }
+
+    #[test]
+    fn evict_keeps_blobs_shared_with_remaining_entries() {
+        let mut index = CacheIndex {
+            budget: 100,
+            entries: HashMap::new(),
+        };
+        index.entries.insert("old".into(), entry("shared", 100, 30));
+        index.entries.insert("other".into(), entry("b", 100, 20));
+        index
+            .entries
+            .insert("alias".into(), entry("shared", 100, 10));
+
+        // Dropping "old" frees nothing since "alias" still points at the blob.
+        assert_eq!(index.evict(), vec!["b".to_string()]);
+        assert_eq!(index.usage(), 100);
+        assert!(index.entries.contains_key("alias"));
+    }
+
+    #[test]
+    fn evict_is_a_no_op_within_budget() {
+        let mut index = CacheIndex::default();
+        index.entries.insert("a".into(), entry("a", 100, 0));
+        assert!(index.evict().is_empty());
+        assert_eq!(index.entries.len(), 1);
+    }
+}
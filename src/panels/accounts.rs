@@ -1,4 +1,5 @@
 use crate::app::ChatClient;
+use crate::theme;
 use eframe::egui::{self, RichText, ScrollArea};
 
 pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
@@ -6,9 +7,9 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
         return;
     }
 
-    egui::SidePanel::left("accounts")
+    let response = egui::SidePanel::left("accounts")
         .resizable(true)
-        .default_width(200.0)
+        .default_width(client.session.panel_width("accounts", 200.0))
         .show(ctx, |ui| {
             ui.heading("Accounts");
 
@@ -36,10 +37,11 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
                     let selected = client.selected_account == Some(*i);
 
                     ui.horizontal(|ui| {
+                        let colors = theme::colors(ui.ctx());
                         let status_color = if is_connected {
-                            egui::Color32::GREEN
+                            colors.online
                         } else {
-                            egui::Color32::GRAY
+                            colors.offline
                         };
                         ui.label(RichText::new("*").color(status_color));
 
@@ -57,6 +59,7 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
                     client.show_account_popup = true;
                     client.editing_account = None;
                     client.temp_profile = oshatori::Profile::default();
+                    client.temp_prefs = Default::default();
                     client.temp_auth = Vec::new();
                     client.selected_protocol = None;
                 }
@@ -70,6 +73,8 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
                             client.temp_auth = account.auth.clone();
                             client.temp_profile =
                                 account.private_profile.clone().unwrap_or_default();
+                            client.temp_prefs =
+                                cache.account_prefs.get(idx).cloned().unwrap_or_default();
                             client.selected_protocol = client
                                 .protocols
                                 .iter()
@@ -83,7 +88,9 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
                         {
                             let mut cache = client.cache.lock().unwrap();
                             cache.accounts.remove(idx);
+                            cache.account_prefs.remove(idx);
                             crate::utils::save_accounts(&cache.accounts);
+                            crate::utils::save_account_prefs(&cache.account_prefs);
                         }
                         client.selected_account = None;
                     }
@@ -119,4 +126,8 @@ pub fn draw_accounts(client: &mut ChatClient, ctx: &egui::Context) {
                 }
             }
         });
+    client
+        .session
+        .panel_widths
+        .insert("accounts".to_string(), response.response.rect.width());
 }
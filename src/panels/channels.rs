@@ -1,5 +1,7 @@
 use crate::app::ChatClient;
-use eframe::egui::{self, ScrollArea};
+use crate::state::{AccountPrefs, ChannelCommand, ConnectionCache, NotifyLevel};
+use crate::theme;
+use eframe::egui::{self, Color32, RichText, ScrollArea};
 use oshatori::client::ConnectionStatus;
 
 pub fn draw_channels(client: &mut ChatClient, ctx: &egui::Context) {
@@ -7,9 +9,9 @@ pub fn draw_channels(client: &mut ChatClient, ctx: &egui::Context) {
         return;
     }
 
-    egui::SidePanel::left("channels")
+    let response = egui::SidePanel::left("channels")
         .resizable(true)
-        .default_width(220.0)
+        .default_width(client.session.panel_width("channels", 220.0))
         .show(ctx, |ui| {
             let cache = client.cache.lock().unwrap();
             let connections: Vec<_> = cache.connections.values().cloned().collect();
@@ -49,31 +51,293 @@ pub fn draw_channels(client: &mut ChatClient, ctx: &egui::Context) {
             });
 
             ui.separator();
-            ui.heading("Channels");
+            ui.horizontal(|ui| {
+                ui.heading("Channels");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("Browse").clicked() {
+                        client.show_channel_browser = true;
+                    }
+                    if ui
+                        .small_button("+")
+                        .on_hover_text("Join or create")
+                        .clicked()
+                    {
+                        client.show_channel_popup = true;
+                    }
+                });
+            });
             ui.separator();
 
             if let Some(active_id) = &active_conn {
                 if let Some(conn) = connections.iter().find(|c| &c.connection_id == active_id) {
+                    let prefs = client.account_prefs(conn.account_index);
+                    let mut channels = conn.channels.clone();
+                    prefs.sort_channels(&mut channels);
+
+                    let mut folders: Vec<&String> = prefs
+                        .channel_folders
+                        .iter()
+                        .filter(|(id, _)| channels.contains(id))
+                        .map(|(_, folder)| folder)
+                        .collect();
+                    folders.sort();
+                    folders.dedup();
+
+                    let mut dropped = None;
                     ScrollArea::vertical().show(ui, |ui| {
-                        for channel_id in &conn.channels {
-                            let display = if channel_id.is_empty() {
-                                "General"
-                            } else {
-                                channel_id
-                            };
-
-                            let is_current = conn
-                                .current_channel
-                                .as_ref()
-                                .map(|ch| &ch.channel.id == channel_id)
-                                .unwrap_or(false);
-
-                            if ui.selectable_label(is_current, display).clicked() {
-                                client.sync_selection(channel_id.clone());
+                        let favorites: Vec<&String> =
+                            channels.iter().filter(|id| prefs.is_favorite(id)).collect();
+                        if !favorites.is_empty() {
+                            ui.label(RichText::new("★ Favorites").small().color(Color32::GRAY));
+                            for channel_id in favorites {
+                                draw_channel_row(
+                                    client,
+                                    ui,
+                                    conn,
+                                    &prefs,
+                                    &folders,
+                                    channel_id,
+                                    &mut dropped,
+                                );
                             }
+                            ui.add_space(4.0);
                         }
+
+                        for folder in &folders {
+                            let header = egui::CollapsingHeader::new(folder.as_str())
+                                .id_salt(("channel_folder", folder.as_str()))
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    for channel_id in channels.iter().filter(|id| {
+                                        !prefs.is_favorite(id)
+                                            && prefs.channel_folders.get(*id) == Some(*folder)
+                                    }) {
+                                        draw_channel_row(
+                                            client,
+                                            ui,
+                                            conn,
+                                            &prefs,
+                                            &folders,
+                                            channel_id,
+                                            &mut dropped,
+                                        );
+                                    }
+                                });
+                            if let Some(channel_id) =
+                                header.header_response.dnd_release_payload::<String>()
+                            {
+                                dropped = Some((
+                                    channel_id.as_ref().clone(),
+                                    DropTarget::Folder(folder.to_string()),
+                                ));
+                            }
+                        }
+
+                        for channel_id in channels.iter().filter(|id| {
+                            !prefs.is_favorite(id) && !prefs.channel_folders.contains_key(*id)
+                        }) {
+                            draw_channel_row(
+                                client,
+                                ui,
+                                conn,
+                                &prefs,
+                                &folders,
+                                channel_id,
+                                &mut dropped,
+                            );
+                        }
+                    });
+
+                    if let Some((channel_id, target)) = dropped {
+                        client.update_account_prefs(conn.account_index, |p| match target {
+                            DropTarget::Before(before) => {
+                                if before != channel_id {
+                                    p.move_channel(&channel_id, &before, &channels);
+                                }
+                            }
+                            DropTarget::Folder(folder) => {
+                                p.favorite_channels.retain(|id| id != &channel_id);
+                                p.channel_folders.insert(channel_id, folder);
+                            }
+                        });
+                    }
+                }
+            }
+        });
+    client
+        .session
+        .panel_widths
+        .insert("channels".to_string(), response.response.rect.width());
+}
+
+enum DropTarget {
+    Before(String),
+    Folder(String),
+}
+
+fn draw_channel_row(
+    client: &mut ChatClient,
+    ui: &mut egui::Ui,
+    conn: &ConnectionCache,
+    prefs: &AccountPrefs,
+    folders: &[&String],
+    channel_id: &String,
+    dropped: &mut Option<(String, DropTarget)>,
+) {
+    let display = if channel_id.is_empty() {
+        "General"
+    } else {
+        channel_id
+    };
+
+    let is_current = conn
+        .current_channel
+        .as_ref()
+        .map(|ch| &ch.channel.id == channel_id)
+        .unwrap_or(false);
+
+    let muted = prefs.is_muted(channel_id);
+    let favorite = prefs.is_favorite(channel_id);
+    let unread = conn.unread.get(channel_id).copied().unwrap_or(0);
+    let highlights = conn.highlights.get(channel_id).copied().unwrap_or(0);
+
+    let mut text = RichText::new(if unread > 0 {
+        format!("{} ({})", display, unread)
+    } else {
+        display.to_string()
+    });
+    if muted {
+        text = text.color(Color32::GRAY).italics();
+    } else if highlights > 0 {
+        text = text.color(theme::colors(ui.ctx()).mention).strong();
+    } else if unread > 0 {
+        text = text.strong();
+    }
+
+    let row = ui.horizontal(|ui| {
+        ui.dnd_drag_source(
+            egui::Id::new(("channel_drag", channel_id)),
+            channel_id.clone(),
+            |ui| ui.label(RichText::new("⠿").color(Color32::GRAY)),
+        );
+
+        let response = ui.selectable_label(is_current, text);
+        if response.clicked() {
+            client.sync_selection(channel_id.clone());
+        }
+        response.context_menu(|ui| {
+            if ui
+                .button(if favorite { "Unfavorite" } else { "Favorite" })
+                .clicked()
+            {
+                client.update_account_prefs(conn.account_index, |p| {
+                    if favorite {
+                        p.favorite_channels.retain(|id| id != channel_id);
+                    } else {
+                        p.favorite_channels.push(channel_id.clone());
+                    }
+                });
+                ui.close_menu();
+            }
+            ui.menu_button("Move to folder", |ui| {
+                for folder in folders {
+                    if ui.button(folder.as_str()).clicked() {
+                        *dropped =
+                            Some((channel_id.clone(), DropTarget::Folder(folder.to_string())));
+                        ui.close_menu();
+                    }
+                }
+                if prefs.channel_folders.contains_key(channel_id)
+                    && ui.button("No folder").clicked()
+                {
+                    client.update_account_prefs(conn.account_index, |p| {
+                        p.channel_folders.remove(channel_id);
+                    });
+                    ui.close_menu();
+                }
+                ui.separator();
+                let draft_id = egui::Id::new("new_folder_name");
+                let mut draft = ui.data(|d| d.get_temp::<String>(draft_id).unwrap_or_default());
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut draft)
+                            .desired_width(100.0)
+                            .hint_text("New folder"),
+                    );
+                    if ui.button("Add").clicked() && !draft.trim().is_empty() {
+                        *dropped = Some((
+                            channel_id.clone(),
+                            DropTarget::Folder(draft.trim().to_string()),
+                        ));
+                        draft.clear();
+                        ui.close_menu();
+                    }
+                });
+                ui.data_mut(|d| d.insert_temp(draft_id, draft));
+            });
+            if ui.button("Open in split view").clicked() {
+                client.split_channel(conn.account_index, channel_id.clone());
+                ui.close_menu();
+            }
+            if ui.button("Pop out").clicked() {
+                client.pop_out(conn.account_index, channel_id.clone());
+                ui.close_menu();
+            }
+            ui.menu_button("Notifications", |ui| {
+                let current = prefs.channel_notify.get(channel_id).copied();
+                if ui
+                    .selectable_label(
+                        current.is_none(),
+                        format!("Account default ({})", prefs.notify_level.label()),
+                    )
+                    .clicked()
+                {
+                    client.update_account_prefs(conn.account_index, |p| {
+                        p.channel_notify.remove(channel_id);
                     });
+                    ui.close_menu();
+                }
+                for level in NotifyLevel::ALL {
+                    if ui
+                        .selectable_label(current == Some(level), level.label())
+                        .clicked()
+                    {
+                        client.update_account_prefs(conn.account_index, |p| {
+                            p.channel_notify.insert(channel_id.clone(), level);
+                        });
+                        ui.close_menu();
+                    }
                 }
+            });
+            if ui.button(if muted { "Unmute" } else { "Mute" }).clicked() {
+                client.update_account_prefs(conn.account_index, |p| {
+                    if muted {
+                        p.muted_channels.retain(|id| id != channel_id);
+                    } else {
+                        p.muted_channels.push(channel_id.clone());
+                    }
+                });
+                ui.close_menu();
+            }
+            if client.capabilities(conn.account_index).leave_channels
+                && ui.button("Leave").clicked()
+            {
+                client.run_channel_command(ChannelCommand::Leave(Some(channel_id.clone())));
+                ui.close_menu();
             }
         });
+    });
+
+    let response = row.response;
+    if response.dnd_hover_payload::<String>().is_some() {
+        let rect = response.rect;
+        ui.painter()
+            .hline(rect.x_range(), rect.top(), ui.visuals().selection.stroke);
+    }
+    if let Some(dragged) = response.dnd_release_payload::<String>() {
+        *dropped = Some((
+            dragged.as_ref().clone(),
+            DropTarget::Before(channel_id.clone()),
+        ));
+    }
 }
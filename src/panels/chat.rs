@@ -1,234 +1,897 @@
 use crate::app::ChatClient;
-use crate::state::ChatSettings;
-use crate::utils::color32;
-use chrono::Utc;
-use eframe::egui::{self, Color32, Image, RichText, ScrollArea, TextEdit, Ui};
+use crate::fonts;
+use crate::preview::{load_preview, PreviewPoll};
+use crate::state::{
+    AccountPrefs, ChannelCommand, ChannelPane, ChatSettings, ConnectionCache, MessageLayout,
+    PaneState, SplitDirection, Trust,
+};
+use crate::theme;
+use crate::utils::{format_timestamp, full_timestamp, message_key, message_text, user_color};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use eframe::egui::{
+    self,
+    text::{CCursor, CCursorRange},
+    Color32, Image, RichText, ScrollArea, TextEdit, Ui, UiBuilder,
+};
 use oshatori::{
-    client::ConnectionStatus,
+    client::{ChannelState, ConnectionStatus},
     connection::{ChatEvent, ConnectionEvent},
-    Asset, Message, MessageFragment, MessageStatus, MessageType,
+    Asset, ChannelType, Message, MessageFragment, MessageStatus, MessageType, Profile,
 };
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const IRC_NICK_WIDTH: f32 = 120.0;
+const PANE_GAP: f32 = 8.0;
+
+struct MessageContext<'a> {
+    users: &'a HashMap<String, Profile>,
+    assets: &'a HashMap<String, Asset>,
+    settings: &'a ChatSettings,
+    prefs: &'a AccountPrefs,
+    channel_id: Option<&'a str>,
+    current_user_id: Option<&'a str>,
+    can_edit: bool,
+    messages: &'a [&'a Message],
+    scroll_to: Option<&'a str>,
+    highlight: Option<&'a str>,
+}
+
+struct ReplyQuote {
+    name: String,
+    snippet: String,
+}
+
+const REPLY_PREFIX: &str = "> ";
+const REPLY_SNIPPET_LEN: usize = 50;
+
+enum MessageAction {
+    Reply(Message),
+    JumpTo(String),
+    Quote(Message),
+    OpenProfile(String),
+    Hide(String),
+    ViewSource(Message),
+    Edit(Message),
+    Delete(Message),
+}
 
 pub fn draw_chat(client: &mut ChatClient, ctx: &egui::Context) {
     if client.panels.chat {
         egui::CentralPanel::default().show(ctx, |ui| {
-            let conn = client.active_connection();
-
-            if let Some(ref conn) = conn {
-                if conn.status == ConnectionStatus::Connecting {
-                    ui.vertical_centered(|ui| {
-                        ui.add_space(50.0);
-                        ui.heading(
-                            RichText::new("Connecting...")
-                                .size(32.0)
-                                .color(Color32::YELLOW),
-                        );
-                        ui.add_space(10.0);
-                        ui.label(RichText::new("Please wait").color(Color32::GRAY));
-                        ui.add_space(50.0);
-                    });
-                    return;
+            // Panes for accounts that have since been deleted are dropped.
+            let panes: Vec<(usize, ChannelPane)> = client
+                .session
+                .panes
+                .clone()
+                .into_iter()
+                .filter_map(|pane| Some((client.find_account(&pane.account)?, pane)))
+                .collect();
+            if panes.len() < client.session.panes.len() {
+                client.session.panes = panes.iter().map(|(_, pane)| pane.clone()).collect();
+            }
+            if panes.is_empty() {
+                draw_main_chat(client, ctx, ui);
+                return;
+            }
+
+            let rects = split_rects(
+                ui.available_rect_before_wrap(),
+                panes.len() + 1,
+                client.session.split,
+            );
+            for rect in &rects[1..] {
+                let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+                match client.session.split {
+                    SplitDirection::Horizontal => {
+                        ui.painter()
+                            .vline(rect.left() - PANE_GAP / 2.0, rect.y_range(), stroke);
+                    }
+                    SplitDirection::Vertical => {
+                        ui.painter()
+                            .hline(rect.x_range(), rect.top() - PANE_GAP / 2.0, stroke);
+                    }
+                }
+            }
+
+            ui.allocate_new_ui(UiBuilder::new().max_rect(rects[0]), |ui| {
+                draw_main_chat(client, ctx, ui);
+            });
+            for ((account_index, pane), rect) in panes.iter().zip(&rects[1..]) {
+                let id = egui::Id::new(("pane", &pane.account, &pane.channel_id));
+                let closed = ui
+                    .allocate_new_ui(UiBuilder::new().max_rect(*rect), |ui| {
+                        draw_channel_pane(client, ui, *account_index, &pane.channel_id, id, true)
+                    })
+                    .inner;
+                if closed {
+                    client.session.panes.retain(|p| p != pane);
+                    client.pane_states.remove(&id);
                 }
             }
+        });
+    }
+}
+
+fn split_rects(rect: egui::Rect, count: usize, split: SplitDirection) -> Vec<egui::Rect> {
+    let gaps = PANE_GAP * (count - 1) as f32;
+    (0..count)
+        .map(|i| match split {
+            SplitDirection::Horizontal => {
+                let width = (rect.width() - gaps) / count as f32;
+                let left = rect.left() + i as f32 * (width + PANE_GAP);
+                egui::Rect::from_x_y_ranges(left..=left + width, rect.y_range())
+            }
+            SplitDirection::Vertical => {
+                let height = (rect.height() - gaps) / count as f32;
+                let top = rect.top() + i as f32 * (height + PANE_GAP);
+                egui::Rect::from_x_y_ranges(rect.x_range(), top..=top + height)
+            }
+        })
+        .collect()
+}
 
-            let available_height = {
-                if client.panels.input {
-                    ui.available_height() - 80.0
+fn draw_main_chat(client: &mut ChatClient, ctx: &egui::Context, ui: &mut Ui) {
+    let conn = client.active_connection();
+    let prefs = conn
+        .as_ref()
+        .map(|c| client.account_prefs(c.account_index))
+        .unwrap_or_default();
+
+    if let Some(ref conn) = conn {
+        if conn.status == ConnectionStatus::Connecting {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+                ui.heading(
+                    RichText::new("Connecting...")
+                        .size(32.0)
+                        .color(theme::colors(ctx).server),
+                );
+                ui.add_space(10.0);
+                ui.label(RichText::new("Please wait").color(Color32::GRAY));
+                ui.add_space(50.0);
+            });
+            return;
+        }
+    }
+
+    if let Some(conn) = &conn {
+        if let Some(channel_state) = &conn.current_channel {
+            draw_channel_header(client, ui, conn, channel_state);
+        }
+    }
+
+    if let Some(mut query) = client.search.take() {
+        let mut open = true;
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut query)
+                    .id(egui::Id::new("search"))
+                    .desired_width(ui.available_width() - 30.0)
+                    .hint_text("Search messages"),
+            );
+            if response.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                open = false;
+            }
+            if ui.small_button("✕").clicked() {
+                open = false;
+            }
+        });
+        ui.separator();
+        if open {
+            client.search = Some(query);
+        }
+    }
+
+    let available_height = {
+        if client.panels.input {
+            ui.available_height() - 80.0
+        } else {
+            ui.available_height()
+        }
+    };
+
+    let pending = conn
+        .as_ref()
+        .and_then(|c| {
+            let channel_id = &c.current_channel.as_ref()?.channel.id;
+            c.pending_messages.get(channel_id).cloned()
+        })
+        .unwrap_or_default();
+    let current_message_count = conn
+        .as_ref()
+        .and_then(|c| c.current_channel.as_ref())
+        .map(|ch| ch.messages.len())
+        .unwrap_or(0)
+        + pending.len();
+
+    let should_scroll = client.chat_settings.autoscroll
+        && current_message_count > client.chat_settings.last_message_count;
+
+    let scroll_to = client.scroll_to_message.take();
+    let mut action = None;
+    let scroll_output = ScrollArea::vertical()
+        .max_height(available_height)
+        .auto_shrink([false, false])
+        .show(ui, |ui| {
+            ui.set_min_width(ui.available_width());
+
+            if let Some(conn) = &conn {
+                if let Some(channel_state) = &conn.current_channel {
+                    if let Some(a) = draw_message_list(
+                        client,
+                        ui,
+                        conn,
+                        channel_state,
+                        &pending,
+                        client.search.as_deref(),
+                        scroll_to.as_deref(),
+                    ) {
+                        action = Some(a);
+                    }
+
+                    if should_scroll {
+                        ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
+                    }
                 } else {
-                    ui.available_height()
+                    ui.label("Select a channel to start chatting");
                 }
-            };
+            } else {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(50.0);
+                    ui.heading(RichText::new("No active connection").color(Color32::GRAY));
+                    ui.add_space(10.0);
+                    ui.label("Connect to an account to start chatting");
+                });
+            }
+        });
 
-            let current_message_count = conn
-                .as_ref()
-                .and_then(|c| c.current_channel.as_ref())
-                .map(|ch| ch.messages.len())
-                .unwrap_or(0)
-                + conn.as_ref().map(|c| c.pending_messages.len()).unwrap_or(0);
-
-            let should_scroll = client.chat_settings.autoscroll
-                && current_message_count > client.chat_settings.last_message_count;
-
-            let scroll_output = ScrollArea::vertical()
-                .max_height(available_height)
-                .auto_shrink([false, false])
-                .show(ui, |ui| {
-                    ui.set_min_width(ui.available_width());
-
-                    if let Some(conn) = &conn {
-                        if let Some(channel_state) = &conn.current_channel {
-                            let mut all_users = conn.global_users.clone();
-                            for (id, user) in &channel_state.users {
-                                all_users.insert(id.clone(), user.clone());
-                            }
+    client.chat_settings.last_message_count = current_message_count;
+    let _ = scroll_output;
 
-                            let mut last_sender_id: Option<String> = None;
-                            let mut last_message_type: Option<MessageType> = None;
-
-                            for msg in &channel_state.messages {
-                                let mut is_consecutive =
-                                    last_sender_id.as_ref() == msg.sender_id.as_ref();
-
-                                if let Some(last_type) = last_message_type {
-                                    if last_type != msg.message_type
-                                        || msg.message_type == MessageType::Server
-                                        || msg.message_type == MessageType::Meta
-                                    {
-                                        is_consecutive = false;
-                                    }
-                                }
-                                if msg.message_type == MessageType::Server
-                                    || msg.message_type == MessageType::Meta
-                                {
-                                    is_consecutive = false;
-                                }
-
-                                draw_message(
-                                    ui,
-                                    msg,
-                                    &all_users,
-                                    &conn.assets,
-                                    is_consecutive,
-                                    &client.chat_settings,
-                                );
-                                last_sender_id = msg.sender_id.clone();
-                                last_message_type = Some(msg.message_type.clone());
-                            }
+    if let Some(action) = action {
+        handle_action(client, action);
+    }
 
-                            for msg in &conn.pending_messages {
-                                let mut is_consecutive =
-                                    last_sender_id.as_ref() == msg.sender_id.as_ref();
-                                if let Some(last_type) = last_message_type {
-                                    if last_type != msg.message_type
-                                        || msg.message_type == MessageType::Server
-                                        || msg.message_type == MessageType::Meta
-                                    {
-                                        is_consecutive = false;
-                                    }
-                                }
-
-                                draw_message(
-                                    ui,
-                                    msg,
-                                    &all_users,
-                                    &conn.assets,
-                                    is_consecutive,
-                                    &client.chat_settings,
-                                );
-                                last_sender_id = msg.sender_id.clone();
-                                last_message_type = Some(msg.message_type.clone());
-                            }
+    ui.separator();
 
-                            if should_scroll {
-                                ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
-                            }
-                        } else {
-                            ui.label("Select a channel to start chatting");
+    if client.panels.input {
+        if let Some(conn) = &conn {
+            if let Some(user) = &conn.current_user {
+                let name = user
+                    .display_name
+                    .as_ref()
+                    .or(user.username.as_ref())
+                    .map(|s| s.as_str())
+                    .unwrap_or("Unknown");
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(format!("Sending as: {}", name))
+                            .small()
+                            .color(Color32::GRAY),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("UNEMBED ALL").clicked() {
+                            client.chat_settings.embed_generation += 1;
+                            client.chat_settings.unembed_override = true;
                         }
-                    } else {
-                        ui.vertical_centered(|ui| {
-                            ui.add_space(50.0);
-                            ui.heading(RichText::new("No active connection").color(Color32::GRAY));
-                            ui.add_space(10.0);
-                            ui.label("Connect to an account to start chatting");
-                        });
-                    }
+                        ui.checkbox(&mut client.chat_settings.autoscroll, "Autoscroll");
+                    });
                 });
+            }
+        }
 
-            client.chat_settings.last_message_count = current_message_count;
-            let _ = scroll_output;
+        if let Some(parent) = &client.replying_to {
+            let name = parent
+                .sender_id
+                .as_ref()
+                .map(|id| prefs.display_name(id, client.find_user(id).as_ref()))
+                .unwrap_or_else(|| "Unknown".to_string());
+            if composer_banner(ui, &format!("Replying to {}", name)) {
+                client.replying_to = None;
+            }
+        }
 
-            ui.separator();
+        if client.editing_message.is_some() && composer_banner(ui, "Editing message") {
+            client.editing_message = None;
+            client.new_message.clear();
+        }
 
-            if client.panels.input {
+        ui.horizontal(|ui| {
+            if ui.button("+").on_hover_text("Insert asset").clicked() {
+                client.show_asset_picker = !client.show_asset_picker;
+            }
+
+            let composer_id = egui::Id::new("composer");
+            let complete = ui.memory(|m| m.has_focus(composer_id))
+                && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab));
+            if complete {
                 if let Some(conn) = &conn {
-                    if let Some(user) = &conn.current_user {
-                        let name = user
-                            .display_name
-                            .as_ref()
-                            .or(user.username.as_ref())
-                            .map(|s| s.as_str())
-                            .unwrap_or("Unknown");
-                        ui.horizontal(|ui| {
-                            ui.label(
-                                RichText::new(format!("Sending as: {}", name))
-                                    .small()
-                                    .color(Color32::GRAY),
-                            );
-                            ui.with_layout(
-                                egui::Layout::right_to_left(egui::Align::Center),
-                                |ui| {
-                                    if ui.button("UNEMBED ALL").clicked() {
-                                        client.chat_settings.embed_generation += 1;
-                                        client.chat_settings.unembed_override = true;
-                                    }
-                                    ui.checkbox(&mut client.chat_settings.autoscroll, "Autoscroll");
-                                },
-                            );
-                        });
+                    let mut users = conn.global_users.clone();
+                    if let Some(ch) = &conn.current_channel {
+                        users.extend(ch.users.clone());
                     }
+                    complete_name(&mut client.new_message, &users, &prefs);
                 }
+            }
 
-                ui.horizontal(|ui| {
-                    if ui.button("+").on_hover_text("Insert asset").clicked() {
-                        client.show_asset_picker = !client.show_asset_picker;
-                    }
+            // Edits may span lines, so the editor grows and Enter saves while
+            // Shift+Enter inserts a newline.
+            let editing = client.editing_message.is_some();
+            let submit = editing
+                && ui.memory(|m| m.has_focus(composer_id))
+                && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+            let editor = if editing {
+                TextEdit::multiline(&mut client.new_message).desired_rows(1)
+            } else {
+                TextEdit::singleline(&mut client.new_message)
+            };
+            let mut output = editor
+                .id(composer_id)
+                .desired_width(ui.available_width() - 80.0)
+                .hint_text("Type a message...")
+                .show(ui);
+            if complete {
+                let end = CCursor::new(client.new_message.chars().count());
+                output
+                    .state
+                    .cursor
+                    .set_char_range(Some(CCursorRange::one(end)));
+                output.state.store(ui.ctx(), composer_id);
+            }
+            let response = output.response;
+
+            if response.has_focus()
+                && client.new_message.is_empty()
+                && ui.input(|i| i.key_pressed(egui::Key::ArrowUp))
+            {
+                if let Some(msg) = last_editable_message(client) {
+                    handle_action(client, MessageAction::Edit(msg));
+                }
+            }
+            if client.editing_message.is_some() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                client.editing_message = None;
+                client.new_message.clear();
+            }
+
+            let send = ui.button("Send").clicked()
+                || submit
+                || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
 
-                    let response = ui.add(
-                        TextEdit::singleline(&mut client.new_message)
-                            .desired_width(ui.available_width() - 80.0)
-                            .hint_text("Type a message..."),
+            if send && !client.new_message.trim().is_empty() {
+                response.request_focus();
+                if let Some(conn) = client.active_connection() {
+                    let text = std::mem::take(&mut client.new_message);
+                    let reply_to = client.replying_to.take();
+                    let editing = client.editing_message.take();
+                    send_composer(
+                        client,
+                        &conn,
+                        conn.current_channel.as_ref(),
+                        &text,
+                        reply_to.as_ref(),
+                        editing,
                     );
+                }
+            }
+        });
+    }
 
-                    let send = ui.button("Send").clicked()
-                        || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
-
-                    if send && !client.new_message.trim().is_empty() {
-                        response.request_focus();
-                        if let Some(conn) = client.active_connection() {
-                            let sender_id = conn.current_user.as_ref().and_then(|u| u.id.clone());
-
-                            let message = Message {
-                                id: None,
-                                sender_id: sender_id.clone(),
-                                content: vec![MessageFragment::Text(client.new_message.clone())],
-                                timestamp: Utc::now(),
-                                message_type: MessageType::CurrentUser,
-                                status: MessageStatus::Sent,
-                            };
+    if client.show_asset_picker {
+        if let Some(conn) = &conn {
+            draw_asset_picker(client, ctx, &conn.assets, &prefs);
+        }
+    }
+}
 
-                            client.add_pending_message(message.clone());
+fn draw_message_list(
+    client: &ChatClient,
+    ui: &mut Ui,
+    conn: &ConnectionCache,
+    channel_state: &ChannelState,
+    pending: &[Message],
+    search: Option<&str>,
+    scroll_to: Option<&str>,
+) -> Option<MessageAction> {
+    let prefs = &client.account_prefs(conn.account_index);
+    let mut action = None;
+    let mut all_users = conn.global_users.clone();
+    for (id, user) in &channel_state.users {
+        all_users.insert(id.clone(), user.clone());
+    }
 
-                            let event = ConnectionEvent::Chat {
-                                event: ChatEvent::New {
-                                    channel_id: conn
-                                        .current_channel
-                                        .as_ref()
-                                        .map(|ch| ch.channel.id.clone()),
-                                    message,
-                                },
-                            };
+    let mut last_sender_id: Option<String> = None;
+    let mut last_timestamp: Option<DateTime<Utc>> = None;
+    let mut last_message_type: Option<MessageType> = None;
+    let mut last_day: Option<NaiveDate> = None;
 
-                            client.send_event(event);
-                            client.new_message.clear();
-                        }
-                    }
+    let mut messages: Vec<&Message> = channel_state.messages.iter().collect();
+    if client.chat_settings.show_deleted_messages {
+        if let Some(tombstones) = conn.tombstones.get(&channel_state.channel.id) {
+            messages.extend(tombstones);
+            messages.sort_by_key(|m| m.timestamp);
+        }
+    } else {
+        messages.retain(|m| !matches!(m.status, MessageStatus::Deleted));
+    }
+    if let Some(query) = search.filter(|q| !q.trim().is_empty()) {
+        let query = query.to_lowercase();
+        messages.retain(|m| {
+            message_text(&m.content, &conn.assets)
+                .to_lowercase()
+                .contains(&query)
+        });
+    }
+
+    let cx = MessageContext {
+        users: &all_users,
+        assets: &conn.assets,
+        settings: &client.chat_settings,
+        prefs,
+        channel_id: Some(channel_state.channel.id.as_str()),
+        current_user_id: conn.current_user.as_ref().and_then(|u| u.id.as_deref()),
+        can_edit: client.capabilities(conn.account_index).edit_messages,
+        messages: &messages,
+        scroll_to,
+        highlight: client
+            .highlighted_message
+            .as_ref()
+            .filter(|(_, since)| since.elapsed() < Duration::from_secs(2))
+            .map(|(key, _)| key.as_str()),
+    };
+
+    let mut ignored_run: Vec<&Message> = Vec::new();
+    for msg in messages.iter().copied() {
+        if client.hidden_messages.contains(&message_key(msg)) {
+            continue;
+        }
+        if msg
+            .sender_id
+            .as_ref()
+            .is_some_and(|id| prefs.is_ignored(id, all_users.get(id)))
+        {
+            if client.chat_settings.collapse_ignored {
+                ignored_run.push(msg);
+            }
+            continue;
+        }
+        if !ignored_run.is_empty() {
+            if let Some(a) = draw_ignored_messages(ui, &ignored_run, &cx) {
+                action = Some(a);
+            }
+            ignored_run.clear();
+            last_sender_id = None;
+        }
+
+        let mut is_consecutive = last_sender_id.as_ref() == msg.sender_id.as_ref()
+            && in_group_window(last_timestamp, msg, &client.chat_settings);
+
+        if let Some(last_type) = last_message_type {
+            if last_type != msg.message_type
+                || msg.message_type == MessageType::Server
+                || msg.message_type == MessageType::Meta
+            {
+                is_consecutive = false;
+            }
+        }
+        if msg.message_type == MessageType::Server || msg.message_type == MessageType::Meta {
+            is_consecutive = false;
+        }
+
+        let day = msg.timestamp.with_timezone(&Local).date_naive();
+        if client.chat_settings.day_separators && last_day != Some(day) {
+            draw_day_separator(ui, day);
+            is_consecutive = false;
+        }
+        last_day = Some(day);
+
+        if let Some(a) = draw_message(ui, msg, is_consecutive, &cx) {
+            action = Some(a);
+        }
+        last_sender_id = msg.sender_id.clone();
+        last_timestamp = Some(msg.timestamp);
+        last_message_type = Some(msg.message_type.clone());
+    }
+
+    if !ignored_run.is_empty() {
+        if let Some(a) = draw_ignored_messages(ui, &ignored_run, &cx) {
+            action = Some(a);
+        }
+        last_sender_id = None;
+    }
+
+    for msg in pending {
+        let mut is_consecutive = last_sender_id.as_ref() == msg.sender_id.as_ref()
+            && in_group_window(last_timestamp, msg, &client.chat_settings);
+        if let Some(last_type) = last_message_type {
+            if last_type != msg.message_type
+                || msg.message_type == MessageType::Server
+                || msg.message_type == MessageType::Meta
+            {
+                is_consecutive = false;
+            }
+        }
+
+        let day = msg.timestamp.with_timezone(&Local).date_naive();
+        if client.chat_settings.day_separators && last_day != Some(day) {
+            draw_day_separator(ui, day);
+            is_consecutive = false;
+        }
+        last_day = Some(day);
+
+        if let Some(a) = draw_message(ui, msg, is_consecutive, &cx) {
+            action = Some(a);
+        }
+        last_sender_id = msg.sender_id.clone();
+        last_timestamp = Some(msg.timestamp);
+        last_message_type = Some(msg.message_type.clone());
+    }
+    action
+}
+
+pub fn draw_channel_pane(
+    client: &mut ChatClient,
+    ui: &mut Ui,
+    account_index: usize,
+    channel_id: &str,
+    id: egui::Id,
+    closable: bool,
+) -> bool {
+    let conn = client.connection_for_account(account_index);
+    let Some((conn, channel_state)) = conn
+        .as_ref()
+        .and_then(|c| Some((c, c.channel_states.get(channel_id)?)))
+    else {
+        let mut closed = false;
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new(if conn.is_some() {
+                    "Loading channel..."
+                } else {
+                    "Not connected"
+                })
+                .color(Color32::GRAY),
+            );
+            if closable {
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    closed = ui.small_button("✕").on_hover_text("Close pane").clicked();
                 });
             }
+        });
+        return closed;
+    };
 
-            if client.show_asset_picker {
-                if let Some(conn) = &conn {
-                    draw_asset_picker(client, ctx, &conn.assets);
-                }
+    let mut conn = conn.clone();
+    conn.assets.extend(channel_state.assets.clone());
+    draw_pane_chat(client, ui, &conn, channel_state, id, closable)
+}
+
+fn draw_pane_chat(
+    client: &mut ChatClient,
+    ui: &mut Ui,
+    conn: &ConnectionCache,
+    channel_state: &ChannelState,
+    id: egui::Id,
+    closable: bool,
+) -> bool {
+    let mut state = client.pane_states.remove(&id).unwrap_or_default();
+    let closed = ui
+        .push_id(id, |ui| {
+            draw_pane_contents(client, ui, conn, channel_state, &mut state, closable)
+        })
+        .inner;
+    if !closed {
+        client.pane_states.insert(id, state);
+    }
+    closed
+}
+
+fn draw_pane_contents(
+    client: &mut ChatClient,
+    ui: &mut Ui,
+    conn: &ConnectionCache,
+    channel_state: &ChannelState,
+    state: &mut PaneState,
+    closable: bool,
+) -> bool {
+    let prefs = client.account_prefs(conn.account_index);
+    let channel_id = channel_state.channel.id.clone();
+
+    let mut closed = false;
+    ui.horizontal(|ui| {
+        ui.heading(
+            RichText::new(match channel_id.as_str() {
+                "" => "General",
+                id => id,
+            })
+            .strong(),
+        );
+        if let Some(name) = conn.current_user.as_ref().and_then(|u| u.username.as_ref()) {
+            ui.label(RichText::new(name).small().color(Color32::GRAY));
+        }
+        if closable {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                closed = ui.small_button("✕").on_hover_text("Close pane").clicked();
+            });
+        }
+    });
+    ui.separator();
+
+    let pending = conn
+        .pending_messages
+        .get(&channel_id)
+        .cloned()
+        .unwrap_or_default();
+    let scroll_to = state.scroll_to_message.take();
+    let mut action = None;
+    ScrollArea::vertical()
+        .max_height(ui.available_height() - 40.0)
+        .auto_shrink([false, false])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            ui.set_min_width(ui.available_width());
+            action = draw_message_list(
+                client,
+                ui,
+                conn,
+                channel_state,
+                &pending,
+                None,
+                scroll_to.as_deref(),
+            );
+        });
+
+    match action {
+        Some(MessageAction::Reply(parent)) => {
+            state.editing_message = None;
+            state.replying_to = Some(parent);
+        }
+        Some(MessageAction::JumpTo(key)) => {
+            state.scroll_to_message = Some(key.clone());
+            client.highlighted_message = Some((key, Instant::now()));
+        }
+        Some(MessageAction::Quote(msg)) => {
+            let text = message_text(&msg.content, &conn.assets).replace('\n', " ");
+            state.composer = format!("> {} {}", text.trim(), state.composer);
+        }
+        Some(MessageAction::OpenProfile(user_id)) => {
+            client.set_active_connection(conn.connection_id.clone());
+            client.profile_user = Some(user_id);
+        }
+        Some(MessageAction::Edit(msg)) => {
+            state.replying_to = None;
+            state.composer = editable_text(&msg, &conn.assets);
+            state.editing_message = Some(msg);
+        }
+        Some(MessageAction::Delete(msg)) => {
+            delete_message(client, &conn.connection_id, Some(channel_id.clone()), msg);
+        }
+        Some(action) => handle_action(client, action),
+        None => {}
+    }
+
+    ui.separator();
+    if let Some(parent) = &state.replying_to {
+        let mut users = conn.global_users.clone();
+        users.extend(channel_state.users.clone());
+        let name = sender_name(parent, &users, &prefs);
+        if composer_banner(ui, &format!("Replying to {}", name)) {
+            state.replying_to = None;
+        }
+    }
+    if state.editing_message.is_some() && composer_banner(ui, "Editing message") {
+        state.editing_message = None;
+        state.composer.clear();
+    }
+
+    ui.horizontal(|ui| {
+        let composer_id = ui.make_persistent_id("composer");
+        let editing = state.editing_message.is_some();
+        let submit = editing
+            && ui.memory(|m| m.has_focus(composer_id))
+            && ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Enter));
+        let editor = if editing {
+            TextEdit::multiline(&mut state.composer).desired_rows(1)
+        } else {
+            TextEdit::singleline(&mut state.composer)
+        };
+        let response = ui.add(
+            editor
+                .id(composer_id)
+                .desired_width(ui.available_width() - 60.0)
+                .hint_text("Type a message..."),
+        );
+        if editing && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            state.editing_message = None;
+            state.composer.clear();
+        }
+
+        let send = ui.button("Send").clicked()
+            || submit
+            || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
+        if send && !state.composer.trim().is_empty() {
+            response.request_focus();
+            let text = std::mem::take(&mut state.composer);
+            let reply_to = state.replying_to.take();
+            let editing = state.editing_message.take();
+            send_composer(
+                client,
+                conn,
+                Some(channel_state),
+                &text,
+                reply_to.as_ref(),
+                editing,
+            );
+        }
+    });
+    closed
+}
+
+fn composer_banner(ui: &mut Ui, text: &str) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(text).small().color(Color32::GRAY));
+        ui.small_button("Cancel").clicked()
+    })
+    .inner
+}
+
+// Sends a composer's text to `channel` on `conn`: as an edit of `editing`, as
+// a channel command, or as a new message quoting `reply_to`. The main view,
+// split panes and pop-outs all send through here.
+fn send_composer(
+    client: &mut ChatClient,
+    conn: &ConnectionCache,
+    channel: Option<&ChannelState>,
+    text: &str,
+    reply_to: Option<&Message>,
+    editing: Option<Message>,
+) {
+    let conn_id = conn.connection_id.clone();
+    let channel_id = channel.map(|ch| ch.channel.id.clone());
+
+    if let Some(original) = editing {
+        let message_id = original.id.clone().unwrap_or_default();
+        client.send_event_to(
+            conn_id,
+            ConnectionEvent::Chat {
+                event: ChatEvent::Update {
+                    channel_id,
+                    message_id,
+                    new_message: Message {
+                        content: edited_content(&original, text, &conn.assets),
+                        status: MessageStatus::Edited,
+                        ..original
+                    },
+                },
+            },
+        );
+        return;
+    }
+
+    if let Some(command) = ChannelCommand::parse(text) {
+        // A bare /leave in a pane leaves that pane's channel.
+        let command = match command {
+            ChannelCommand::Leave(None) => ChannelCommand::Leave(channel_id),
+            command => command,
+        };
+        client.run_channel_command_on(conn, command);
+        return;
+    }
+
+    let mut text = text.to_string();
+    if let Some(parent) = reply_to {
+        let mut users = conn.global_users.clone();
+        if let Some(ch) = channel {
+            users.extend(ch.users.clone());
+        }
+        let parent_text = message_text(
+            &split_reply(parent)
+                .map(|(_, body)| body)
+                .unwrap_or(parent.content.clone()),
+            &conn.assets,
+        );
+        text = format!(
+            "{}{}",
+            reply_quote(&protocol_name(parent, &users), &parent_text),
+            text
+        );
+    }
+
+    let message = Message {
+        id: Some(Uuid::new_v4().to_string()),
+        sender_id: conn.current_user.as_ref().and_then(|u| u.id.clone()),
+        content: vec![MessageFragment::Text(text)],
+        timestamp: Utc::now(),
+        message_type: MessageType::CurrentUser,
+        status: MessageStatus::Sent,
+    };
+    if let Some(channel_id) = &channel_id {
+        client.add_pending_message(&conn_id, channel_id, message.clone());
+    }
+    client.send_event_to(
+        conn_id,
+        ConnectionEvent::Chat {
+            event: ChatEvent::New {
+                channel_id,
+                message,
+            },
+        },
+    );
+}
+
+fn delete_message(client: &ChatClient, conn_id: &str, channel_id: Option<String>, msg: Message) {
+    if let Some(message_id) = msg.id {
+        client.send_event_to(
+            conn_id.to_string(),
+            ConnectionEvent::Chat {
+                event: ChatEvent::Remove {
+                    channel_id,
+                    message_id,
+                },
+            },
+        );
+    }
+}
+
+fn draw_channel_header(
+    client: &mut ChatClient,
+    ui: &mut Ui,
+    conn: &ConnectionCache,
+    channel_state: &ChannelState,
+) {
+    let channel = &channel_state.channel;
+    let name = channel
+        .name
+        .clone()
+        .unwrap_or_else(|| match channel.id.as_str() {
+            "" => "General".to_string(),
+            id => id.to_string(),
+        });
+
+    ui.horizontal(|ui| {
+        ui.heading(RichText::new(&name).strong());
+        ui.label(
+            RichText::new(channel_kind(&channel.channel_type))
+                .small()
+                .color(Color32::GRAY),
+        );
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            let users = conn
+                .channel_users
+                .get(&channel.id)
+                .map(|u| u.len())
+                .unwrap_or(channel_state.users.len());
+            ui.label(RichText::new(format!("{} users", users)).small());
+            if ui
+                .small_button("⧉")
+                .on_hover_text("Pop out into a new window")
+                .clicked()
+            {
+                client.pop_out(conn.account_index, channel.id.clone());
             }
         });
+    });
+    ui.separator();
+}
+
+// The channel type is the only flag oshatori reports; it has no topics,
+// passwords or permission levels for the header to show.
+fn channel_kind(channel_type: &ChannelType) -> &'static str {
+    match channel_type {
+        ChannelType::Group => "group",
+        ChannelType::Direct => "direct",
+        ChannelType::Broadcast => "broadcast",
     }
 }
 
 fn draw_asset_picker(
     client: &mut ChatClient,
     ctx: &egui::Context,
-    assets: &std::collections::HashMap<String, Asset>,
+    assets: &HashMap<String, Asset>,
+    prefs: &AccountPrefs,
 ) {
     egui::Window::new("Insert asset")
         .collapsible(true)
@@ -292,7 +955,7 @@ fn draw_asset_picker(
                     ui.label(RichText::new("Emotes").strong());
                     ui.horizontal_wrapped(|ui| {
                         for (id, pattern, src) in &emotes {
-                            let response = if !src.is_empty() {
+                            let response = if !src.is_empty() && prefs.auto_embed(src) {
                                 ui.add(
                                     Image::from_uri(src)
                                         .fit_to_exact_size(egui::Vec2::new(32.0, 32.0))
@@ -303,7 +966,7 @@ fn draw_asset_picker(
                                 ui.button(pattern).on_hover_text(id)
                             };
                             if response.clicked() {
-                                client.new_message.push_str(&format!("{}", pattern));
+                                client.new_message.push_str(pattern);
                                 client.show_asset_picker = false;
                             }
                         }
@@ -315,7 +978,7 @@ fn draw_asset_picker(
                     ui.label(RichText::new("Stickers").strong());
                     ui.horizontal_wrapped(|ui| {
                         for (id, pattern, src) in &stickers {
-                            let response = if !src.is_empty() {
+                            let response = if !src.is_empty() && prefs.auto_embed(src) {
                                 ui.add(
                                     Image::from_uri(src)
                                         .max_size(egui::Vec2::new(64.0, 64.0))
@@ -326,7 +989,7 @@ fn draw_asset_picker(
                                 ui.button(pattern).on_hover_text(id)
                             };
                             if response.clicked() {
-                                client.new_message.push_str(&format!("{}", pattern));
+                                client.new_message.push_str(pattern);
                                 client.show_asset_picker = false;
                             }
                         }
@@ -342,7 +1005,7 @@ fn draw_asset_picker(
                             .on_hover_text(id)
                             .clicked()
                         {
-                            client.new_message.push_str(&format!("{}", pattern));
+                            client.new_message.push_str(pattern);
                             client.show_asset_picker = false;
                         }
                     }
@@ -351,116 +1014,637 @@ fn draw_asset_picker(
         });
 }
 
+fn draw_day_separator(ui: &mut Ui, day: NaiveDate) {
+    ui.add_space(6.0);
+    ui.vertical_centered(|ui| {
+        ui.label(
+            RichText::new(day.format("%A, %B %-d, %Y").to_string())
+                .small()
+                .strong()
+                .color(Color32::GRAY),
+        );
+    });
+    ui.add_space(2.0);
+}
+
 fn draw_message(
     ui: &mut Ui,
     msg: &Message,
-    users: &std::collections::HashMap<String, oshatori::Profile>,
-    assets: &std::collections::HashMap<String, Asset>,
     is_consecutive: bool,
-    settings: &ChatSettings,
-) {
-    let sender = msg.sender_id.as_ref().and_then(|id| users.get(id));
+    cx: &MessageContext,
+) -> Option<MessageAction> {
+    let mut action = None;
+    let response = ui
+        .scope_builder(UiBuilder::new().sense(egui::Sense::click()), |ui| {
+            action = match cx.settings.layout {
+                MessageLayout::Cozy => draw_cozy(ui, msg, is_consecutive, cx),
+                MessageLayout::Compact => draw_compact(ui, msg, cx),
+                MessageLayout::Irc => draw_irc(ui, msg, cx),
+            };
+        })
+        .response;
 
-    if matches!(msg.message_type, MessageType::Server | MessageType::Meta) {
-        ui.horizontal(|ui| {
-            ui.add_space(42.0);
-            ui.vertical(|ui| {
-                ui.horizontal_wrapped(|ui| {
-                    ui.label(
-                        RichText::new(msg.timestamp.format("%H:%M:%S").to_string())
-                            .color(Color32::from_gray(100))
-                            .small(),
-                    );
+    let key = message_key(msg);
+    if cx.highlight == Some(key.as_str()) {
+        ui.painter()
+            .rect_filled(response.rect, 2.0, theme::colors(ui.ctx()).highlight);
+    }
+    if cx.scroll_to == Some(key.as_str()) {
+        response.scroll_to_me(Some(egui::Align::Center));
+    }
 
-                    ui.scope(|ui| {
-                        ui.style_mut().visuals.override_text_color = Some(Color32::from_gray(150));
-                        for (i, fragment) in msg.content.iter().enumerate() {
-                            draw_fragment(ui, fragment, assets, settings, msg.id.as_ref(), i);
-                        }
-                    });
-                });
-            });
+    message_menu(&response, msg, cx).or(action)
+}
+
+fn in_group_window(last: Option<DateTime<Utc>>, msg: &Message, settings: &ChatSettings) -> bool {
+    settings.group_window_secs == 0
+        || last.is_some_and(|last| {
+            (msg.timestamp - last).num_seconds() <= settings.group_window_secs as i64
+        })
+}
+
+fn is_system(msg: &Message) -> bool {
+    matches!(msg.message_type, MessageType::Server | MessageType::Meta)
+}
+
+fn timestamp_label(ui: &mut Ui, msg: &Message, text: String) {
+    ui.label(RichText::new(text).color(Color32::from_gray(100)).small())
+        .on_hover_text(full_timestamp(msg.timestamp));
+}
+
+fn draw_system_content(ui: &mut Ui, msg: &Message, cx: &MessageContext) -> Option<MessageAction> {
+    ui.scope(|ui| {
+        ui.style_mut().visuals.override_text_color = Some(Color32::from_gray(150));
+        draw_content(ui, msg, cx)
+    })
+    .inner
+}
+
+fn sender_label(
+    ui: &mut Ui,
+    msg: &Message,
+    cx: &MessageContext,
+    decorate: fn(String) -> String,
+) -> Option<MessageAction> {
+    let sender = msg.sender_id.as_ref().and_then(|id| cx.users.get(id));
+    let name = sender_name(msg, cx.users, cx.prefs);
+    let colors = theme::colors(ui.ctx());
+    let color = msg
+        .sender_id
+        .as_deref()
+        .and_then(|id| user_color(id, sender, cx.prefs, cx.settings, ui.visuals().panel_fill))
+        .unwrap_or(match msg.message_type {
+            MessageType::CurrentUser => colors.own_message,
+            MessageType::Normal => colors.normal,
+            MessageType::Server => colors.server,
+            MessageType::Meta => colors.meta,
         });
-        return;
+
+    let response = ui.add(
+        egui::Label::new(RichText::new(decorate(name)).color(color).strong())
+            .sense(egui::Sense::click()),
+    );
+    msg.sender_id
+        .clone()
+        .filter(|_| response.clicked())
+        .map(MessageAction::OpenProfile)
+}
+
+fn draw_cozy(
+    ui: &mut Ui,
+    msg: &Message,
+    is_consecutive: bool,
+    cx: &MessageContext,
+) -> Option<MessageAction> {
+    let avatar = cx.settings.avatar_size;
+    let indent = avatar + 8.0;
+
+    if is_system(msg) {
+        return ui
+            .horizontal(|ui| {
+                ui.add_space(indent + 2.0);
+                ui.vertical(|ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        timestamp_label(ui, msg, format_timestamp(msg.timestamp, cx.settings));
+                        draw_system_content(ui, msg, cx)
+                    })
+                    .inner
+                })
+                .inner
+            })
+            .inner;
     }
 
     if is_consecutive {
-        ui.horizontal(|ui| {
-            ui.add_space(40.0);
-            ui.vertical(|ui| {
-                ui.horizontal_wrapped(|ui| {
-                    for (i, fragment) in msg.content.iter().enumerate() {
-                        draw_fragment(ui, fragment, assets, settings, msg.id.as_ref(), i);
+        return ui
+            .horizontal(|ui| {
+                ui.add_space(indent);
+                ui.vertical(|ui| ui.horizontal_wrapped(|ui| draw_content(ui, msg, cx)).inner)
+                    .inner
+            })
+            .inner;
+    }
+
+    ui.separator();
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        let picture = msg
+            .sender_id
+            .as_ref()
+            .and_then(|id| cx.users.get(id))
+            .and_then(|p| p.picture.as_ref())
+            .filter(|p| cx.prefs.auto_embed(p));
+        match picture {
+            Some(pic) if avatar > 0.0 => {
+                ui.add(
+                    Image::from_uri(pic)
+                        .fit_to_exact_size(egui::Vec2::splat(avatar))
+                        .rounding(egui::Rounding::same(2.0)),
+                );
+            }
+            _ => ui.add_space(avatar),
+        }
+
+        ui.vertical(|ui| {
+            let mut action = ui
+                .horizontal(|ui| {
+                    let action = sender_label(ui, msg, cx, |name| name);
+                    ui.label(
+                        RichText::new(format!(
+                            "@ {}",
+                            format_timestamp(msg.timestamp, cx.settings)
+                        ))
+                        .color(Color32::GRAY)
+                        .small(),
+                    )
+                    .on_hover_text(full_timestamp(msg.timestamp));
+                    action
+                })
+                .inner;
+
+            if let Some(a) = ui.horizontal_wrapped(|ui| draw_content(ui, msg, cx)).inner {
+                action = Some(a);
+            }
+            action
+        })
+        .inner
+    })
+    .inner
+}
+
+fn draw_compact(ui: &mut Ui, msg: &Message, cx: &MessageContext) -> Option<MessageAction> {
+    ui.horizontal_wrapped(|ui| {
+        timestamp_label(
+            ui,
+            msg,
+            format!("[{}]", format_timestamp(msg.timestamp, cx.settings)),
+        );
+        if is_system(msg) {
+            return draw_system_content(ui, msg, cx);
+        }
+        let action = sender_label(ui, msg, cx, |name| format!("<{}>", name));
+        draw_content(ui, msg, cx).or(action)
+    })
+    .inner
+}
+
+fn draw_irc(ui: &mut Ui, msg: &Message, cx: &MessageContext) -> Option<MessageAction> {
+    ui.horizontal(|ui| {
+        timestamp_label(ui, msg, format_timestamp(msg.timestamp, cx.settings));
+        let column = egui::Vec2::new(IRC_NICK_WIDTH, ui.spacing().interact_size.y);
+        let action = ui
+            .allocate_ui_with_layout(
+                column,
+                egui::Layout::right_to_left(egui::Align::Center),
+                |ui| {
+                    ui.set_min_width(IRC_NICK_WIDTH);
+                    if is_system(msg) {
+                        ui.label(RichText::new("*").color(Color32::from_gray(150)));
+                        None
+                    } else {
+                        sender_label(ui, msg, cx, |name| name)
                     }
-                });
-            });
-        });
-    } else {
-        ui.separator();
-        ui.add_space(4.0);
-        ui.horizontal(|ui| {
-            if let Some(profile) = sender {
-                if let Some(pic) = &profile.picture {
-                    ui.add(
-                        Image::from_uri(pic)
-                            .fit_to_exact_size(egui::Vec2::new(32.0, 32.0))
-                            .rounding(egui::Rounding::same(2.0)),
-                    );
+                },
+            )
+            .inner;
+        ui.label(RichText::new("│").color(Color32::from_gray(80)));
+        let content = ui
+            .horizontal_wrapped(|ui| {
+                if is_system(msg) {
+                    draw_system_content(ui, msg, cx)
                 } else {
-                    ui.add_space(32.0);
+                    draw_content(ui, msg, cx)
                 }
-            } else {
-                ui.add_space(32.0);
+            })
+            .inner;
+        content.or(action)
+    })
+    .inner
+}
+
+fn draw_content(ui: &mut Ui, msg: &Message, cx: &MessageContext) -> Option<MessageAction> {
+    if matches!(msg.status, MessageStatus::Deleted) {
+        ui.label(
+            RichText::new("message deleted")
+                .italics()
+                .color(Color32::from_gray(110)),
+        );
+        return None;
+    }
+
+    let mut action = None;
+    // A quote line only becomes a reply preview when it points at a message
+    // we have; anything else is shown as the sender typed it.
+    let reply = split_reply(msg).and_then(|(quote, body)| {
+        let parent = find_parent(cx.messages, msg, &quote, cx.users, cx.assets)?;
+        Some((quote, parent, body))
+    });
+    let content = match &reply {
+        Some((quote, parent, body)) => {
+            action = draw_reply_preview(ui, quote, parent);
+            ui.end_row();
+            body
+        }
+        None => &msg.content,
+    };
+
+    for (i, fragment) in content.iter().enumerate() {
+        draw_fragment(ui, fragment, cx, msg.id.as_ref(), i);
+    }
+
+    if matches!(msg.status, MessageStatus::Edited) {
+        ui.label(RichText::new("(edited)").small().color(Color32::GRAY))
+            .on_hover_text("This message has been edited");
+    }
+
+    action
+}
+
+fn draw_reply_preview(ui: &mut Ui, quote: &ReplyQuote, parent: &Message) -> Option<MessageAction> {
+    let text = RichText::new(format!("{}{}: {}", REPLY_PREFIX, quote.name, quote.snippet))
+        .small()
+        .italics()
+        .color(Color32::GRAY);
+    let response = ui
+        .add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text("Jump to message");
+    response
+        .clicked()
+        .then(|| MessageAction::JumpTo(message_key(parent)))
+}
+
+// Replies travel as a plain quote line, so the parent is the latest earlier
+// message from the quoted user whose text starts with the snippet.
+fn find_parent<'a>(
+    messages: &[&'a Message],
+    msg: &Message,
+    quote: &ReplyQuote,
+    users: &HashMap<String, Profile>,
+    assets: &HashMap<String, Asset>,
+) -> Option<&'a Message> {
+    let snippet = quote.snippet.trim_end_matches("...");
+    let key = message_key(msg);
+    messages.iter().rev().copied().find(|m| {
+        m.timestamp <= msg.timestamp
+            && message_key(m) != key
+            && protocol_name(m, users) == quote.name
+            && message_text(
+                &split_reply(m)
+                    .map(|(_, body)| body)
+                    .unwrap_or(m.content.clone()),
+                assets,
+            )
+            .replace('\n', " ")
+            .trim()
+            .starts_with(snippet)
+    })
+}
+
+fn reply_quote(name: &str, text: &str) -> String {
+    let text = text.replace('\n', " ");
+    let text = text.trim();
+    let snippet = if text.chars().count() > REPLY_SNIPPET_LEN {
+        format!(
+            "{}...",
+            text.chars().take(REPLY_SNIPPET_LEN).collect::<String>()
+        )
+    } else {
+        text.to_string()
+    };
+    format!("{}{}: {}\n", REPLY_PREFIX, name, snippet)
+}
+
+fn split_reply(msg: &Message) -> Option<(ReplyQuote, Vec<MessageFragment>)> {
+    let Some(MessageFragment::Text(first)) = msg.content.first() else {
+        return None;
+    };
+    let (quote_line, rest) = first.strip_prefix(REPLY_PREFIX)?.split_once('\n')?;
+    let (name, snippet) = quote_line.split_once(": ")?;
+    // Only lines shaped like the ones `reply_quote` writes count.
+    let length = snippet.chars().count();
+    let shaped = match snippet.strip_suffix("...") {
+        Some(_) if length == REPLY_SNIPPET_LEN + 3 => true,
+        _ => length <= REPLY_SNIPPET_LEN,
+    };
+    if name.is_empty() || snippet.trim().is_empty() || !shaped {
+        return None;
+    }
+
+    let mut body = msg.content.clone();
+    if rest.is_empty() {
+        body.remove(0);
+    } else {
+        body[0] = MessageFragment::Text(rest.to_string());
+    }
+
+    Some((
+        ReplyQuote {
+            name: name.to_string(),
+            snippet: snippet.to_string(),
+        },
+        body,
+    ))
+}
+
+// The editor shows the whole body as text, with links, media and assets as
+// their URL or pattern. Those fragments are kept in place wherever their text
+// is still present after the edit, and dropped where it was removed.
+fn editable_text(msg: &Message, assets: &HashMap<String, Asset>) -> String {
+    let body = split_reply(msg)
+        .map(|(_, body)| body)
+        .unwrap_or_else(|| msg.content.clone());
+    message_text(&body, assets)
+}
+
+fn edited_content(
+    original: &Message,
+    text: &str,
+    assets: &HashMap<String, Asset>,
+) -> Vec<MessageFragment> {
+    let (quote, body) = match split_reply(original) {
+        Some((quote, body)) => (Some(quote), body),
+        None => (None, original.content.clone()),
+    };
+
+    let mut content = Vec::new();
+    let mut rest = text;
+    for fragment in body {
+        if matches!(fragment, MessageFragment::Text(_)) {
+            continue;
+        }
+        let shown = message_text(std::slice::from_ref(&fragment), assets);
+        let Some(at) = rest.find(&shown) else {
+            continue;
+        };
+        if at > 0 {
+            content.push(MessageFragment::Text(rest[..at].to_string()));
+        }
+        content.push(fragment);
+        rest = &rest[at + shown.len()..];
+    }
+    if !rest.is_empty() {
+        content.push(MessageFragment::Text(rest.to_string()));
+    }
+
+    if let Some(quote) = quote {
+        let line = format!("{}{}: {}\n", REPLY_PREFIX, quote.name, quote.snippet);
+        match content.first_mut() {
+            Some(MessageFragment::Text(first)) => first.insert_str(0, &line),
+            _ => content.insert(0, MessageFragment::Text(line)),
+        }
+    }
+    content
+}
+
+fn complete_name(text: &mut String, users: &HashMap<String, Profile>, prefs: &AccountPrefs) {
+    let start = text
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    let word = &text[start..];
+    let (at, prefix) = match word.strip_prefix('@') {
+        Some(prefix) => ("@", prefix),
+        None => ("", word),
+    };
+    if prefix.is_empty() {
+        return;
+    }
+
+    let prefix = prefix.to_lowercase();
+    let mut names: Vec<String> = users
+        .iter()
+        .map(|(id, profile)| prefs.display_name(id, Some(profile)))
+        .filter(|name| name.to_lowercase().starts_with(&prefix))
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+
+    if let Some(name) = names.first() {
+        let completed = format!("{}{} ", at, name);
+        text.replace_range(start.., &completed);
+    }
+}
+
+fn draw_ignored_messages(
+    ui: &mut Ui,
+    messages: &[&Message],
+    cx: &MessageContext,
+) -> Option<MessageAction> {
+    let id = egui::Id::new(("ignored_run", message_key(messages[0])));
+    let mut revealed = ui.data(|d| d.get_temp::<bool>(id).unwrap_or(false));
+    let label = if messages.len() == 1 {
+        "1 ignored message".to_string()
+    } else {
+        format!("{} ignored messages", messages.len())
+    };
+
+    let response = ui.add(
+        egui::Label::new(
+            RichText::new(format!("{} {}", if revealed { "▼" } else { "▶" }, label))
+                .small()
+                .italics()
+                .color(Color32::GRAY),
+        )
+        .sense(egui::Sense::click()),
+    );
+    if response.clicked() {
+        revealed = !revealed;
+        ui.data_mut(|d| d.insert_temp(id, revealed));
+    }
+
+    let mut action = None;
+    if revealed {
+        let mut last_sender_id = None;
+        for msg in messages {
+            let is_consecutive = last_sender_id == msg.sender_id.as_ref();
+            if let Some(a) = draw_message(ui, msg, is_consecutive, cx) {
+                action = Some(a);
             }
+            last_sender_id = msg.sender_id.as_ref();
+        }
+    }
+    action
+}
 
-            ui.vertical(|ui| {
-                ui.horizontal(|ui| {
-                    let default = "Unknown".to_string();
-                    let sender_id = msg.sender_id.as_ref().unwrap_or(&default);
-                    let name = sender
-                        .and_then(|p| p.display_name.as_ref().or(p.username.as_ref()))
-                        .unwrap_or(sender_id);
-
-                    let color = sender
-                        .and_then(|p| p.color.map(color32))
-                        .unwrap_or_else(|| match msg.message_type {
-                            MessageType::CurrentUser => Color32::GREEN,
-                            MessageType::Normal => Color32::WHITE,
-                            MessageType::Server => Color32::YELLOW,
-                            MessageType::Meta => Color32::GRAY,
-                        });
-
-                    ui.label(RichText::new(name).color(color).strong());
-                    ui.label(
-                        RichText::new(msg.timestamp.format("@ %H:%M:%S").to_string())
-                            .color(Color32::GRAY)
-                            .small(),
-                    );
-                });
+fn sender_name(msg: &Message, users: &HashMap<String, Profile>, prefs: &AccountPrefs) -> String {
+    let sender_id = msg.sender_id.as_deref().unwrap_or("Unknown");
+    prefs.display_name(sender_id, users.get(sender_id))
+}
 
-                ui.horizontal_wrapped(|ui| {
-                    for (i, fragment) in msg.content.iter().enumerate() {
-                        draw_fragment(ui, fragment, assets, settings, msg.id.as_ref(), i);
-                    }
-                });
-            });
-        });
+// The name other users see for the sender, ignoring local nicknames. Reply
+// lines are sent to the server, so they quote this.
+fn protocol_name(msg: &Message, users: &HashMap<String, Profile>) -> String {
+    let sender_id = msg.sender_id.as_deref().unwrap_or("Unknown");
+    users
+        .get(sender_id)
+        .and_then(|p| p.username.clone())
+        .unwrap_or_else(|| sender_id.to_string())
+}
+
+fn message_menu(
+    response: &egui::Response,
+    msg: &Message,
+    cx: &MessageContext,
+) -> Option<MessageAction> {
+    let mut action = None;
+    response.context_menu(|ui| {
+        if ui.button("Copy text").clicked() {
+            ui.ctx().copy_text(message_text(&msg.content, cx.assets));
+            ui.close_menu();
+        }
+        if ui.button("Copy reference").clicked() {
+            ui.ctx().copy_text(format!(
+                "{}#{}",
+                cx.channel_id.unwrap_or(""),
+                message_key(msg)
+            ));
+            ui.close_menu();
+        }
+        if ui.button("Reply").clicked() {
+            action = Some(MessageAction::Reply(msg.clone()));
+            ui.close_menu();
+        }
+        if ui.button("Quote").clicked() {
+            action = Some(MessageAction::Quote(msg.clone()));
+            ui.close_menu();
+        }
+        if let Some(sender_id) = &msg.sender_id {
+            if ui.button("View profile").clicked() {
+                action = Some(MessageAction::OpenProfile(sender_id.clone()));
+                ui.close_menu();
+            }
+        }
+        if ui.button("Hide").clicked() {
+            action = Some(MessageAction::Hide(message_key(msg)));
+            ui.close_menu();
+        }
+
+        if cx.can_edit && is_own_message(msg, cx.current_user_id) && msg.id.is_some() {
+            ui.separator();
+            if ui.button("Edit").clicked() {
+                action = Some(MessageAction::Edit(msg.clone()));
+                ui.close_menu();
+            }
+            if ui.button("Delete").clicked() {
+                action = Some(MessageAction::Delete(msg.clone()));
+                ui.close_menu();
+            }
+        }
+
+        ui.separator();
+        if ui.button("View source").clicked() {
+            action = Some(MessageAction::ViewSource(msg.clone()));
+            ui.close_menu();
+        }
+    });
+    action
+}
+
+fn is_own_message(msg: &Message, current_user_id: Option<&str>) -> bool {
+    msg.message_type == MessageType::CurrentUser
+        || (msg.sender_id.is_some() && msg.sender_id.as_deref() == current_user_id)
+}
+
+fn last_editable_message(client: &ChatClient) -> Option<Message> {
+    let conn = client.active_connection()?;
+    if !client.capabilities(conn.account_index).edit_messages {
+        return None;
+    }
+    let current_user_id = conn.current_user.as_ref().and_then(|u| u.id.clone());
+    conn.current_channel?.messages.into_iter().rev().find(|m| {
+        m.id.is_some()
+            && !matches!(m.status, MessageStatus::Deleted)
+            && is_own_message(m, current_user_id.as_deref())
+    })
+}
+
+fn handle_action(client: &mut ChatClient, action: MessageAction) {
+    match action {
+        MessageAction::Reply(msg) => {
+            client.editing_message = None;
+            client.replying_to = Some(msg);
+        }
+        MessageAction::JumpTo(key) => {
+            client.scroll_to_message = Some(key.clone());
+            client.highlighted_message = Some((key, Instant::now()));
+        }
+        MessageAction::Quote(msg) => {
+            let assets = client
+                .active_connection()
+                .map(|c| c.assets)
+                .unwrap_or_default();
+            let text = message_text(&msg.content, &assets).replace('\n', " ");
+            client.new_message = format!("> {} {}", text.trim(), client.new_message);
+        }
+        MessageAction::OpenProfile(user_id) => {
+            client.profile_user = Some(user_id);
+        }
+        MessageAction::Hide(key) => {
+            client.hidden_messages.insert(key);
+        }
+        MessageAction::ViewSource(msg) => {
+            client.message_source = Some(msg);
+        }
+        MessageAction::Edit(msg) => {
+            let assets = client
+                .active_connection()
+                .map(|c| c.assets)
+                .unwrap_or_default();
+            client.replying_to = None;
+            client.new_message = editable_text(&msg, &assets);
+            client.editing_message = Some(msg);
+        }
+        MessageAction::Delete(msg) => {
+            if let Some(conn) = client.active_connection() {
+                let channel_id = conn.current_channel.map(|ch| ch.channel.id);
+                delete_message(client, &conn.connection_id, channel_id, msg);
+            }
+        }
     }
 }
 
 fn draw_fragment(
     ui: &mut Ui,
     fragment: &MessageFragment,
-    assets: &std::collections::HashMap<String, Asset>,
-    settings: &ChatSettings,
+    cx: &MessageContext,
     msg_id: Option<&String>,
     index: usize,
 ) {
+    let settings = cx.settings;
+    let prefs = cx.prefs;
+
     match fragment {
         MessageFragment::Text(text) => {
-            ui.label(text);
+            ui.label(RichText::new(text).font(fonts::chat_font(settings)));
         }
         MessageFragment::Image { url, .. } => {
+            if prefs.trust(url) == Trust::Blocked {
+                ui.label(RichText::new("[blocked image]").color(Color32::GRAY))
+                    .on_hover_text(url);
+                return;
+            }
+
             let id = ui.make_persistent_id(format!(
                 "img_{}_{}_{}",
                 msg_id.unwrap_or(&"none".to_string()),
@@ -470,7 +1654,7 @@ fn draw_fragment(
             let default = if settings.unembed_override {
                 false
             } else {
-                settings.auto_embed_images
+                settings.auto_embed_images && prefs.auto_embed(url)
             };
             let mut show = ui.data(|d| d.get_temp(id).unwrap_or(default));
 
@@ -488,12 +1672,11 @@ fn draw_fragment(
             });
 
             if show {
-                ui.add(
-                    Image::from_uri(url)
-                        .max_width(ui.available_width().min(600.0).max(200.0))
-                        .max_height(600.0)
-                        .fit_to_original_size(1.0),
-                );
+                let image = Image::from_uri(url)
+                    .max_width(ui.available_width().clamp(200.0, 600.0))
+                    .max_height(600.0)
+                    .fit_to_original_size(1.0);
+                draw_hidden(ui, image, id, settings);
             }
         }
         MessageFragment::Video { url, .. } => {
@@ -556,10 +1739,13 @@ fn draw_fragment(
         }
         MessageFragment::Url(url) => {
             ui.hyperlink(url);
+            if prefs.preview(url) {
+                draw_link_preview(ui, url, settings, prefs);
+            }
         }
         MessageFragment::AssetId(id) => {
-            if let Some(asset) = assets.get(id) {
-                draw_asset(ui, asset, id, settings);
+            if let Some(asset) = cx.assets.get(id) {
+                draw_asset(ui, asset, id, settings, prefs);
             } else {
                 ui.label(RichText::new(format!("[asset] {}", id)).color(Color32::GRAY));
             }
@@ -567,12 +1753,84 @@ fn draw_fragment(
     }
 }
 
-fn draw_asset(ui: &mut Ui, asset: &Asset, _id: &str, settings: &ChatSettings) {
+fn draw_link_preview(ui: &mut Ui, url: &str, settings: &ChatSettings, prefs: &AccountPrefs) {
+    let PreviewPoll::Ready(preview) = load_preview(ui.ctx(), url) else {
+        return;
+    };
+
+    ui.end_row();
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.set_max_width(ui.available_width().min(480.0));
+        ui.horizontal(|ui| {
+            if let Some(image) = preview.image.as_ref().filter(|i| prefs.auto_embed(i)) {
+                let id = ui.make_persistent_id(("preview_image", url));
+                draw_hidden(
+                    ui,
+                    Image::from_uri(image).max_size(egui::Vec2::new(80.0, 80.0)),
+                    id,
+                    settings,
+                );
+            }
+            ui.vertical(|ui| {
+                if let Some(site_name) = &preview.site_name {
+                    ui.label(RichText::new(site_name).small().color(Color32::GRAY));
+                }
+                if let Some(title) = &preview.title {
+                    ui.hyperlink_to(RichText::new(title).strong(), url);
+                }
+                if let Some(description) = &preview.description {
+                    let description = if description.chars().count() > 200 {
+                        format!("{}...", description.chars().take(200).collect::<String>())
+                    } else {
+                        description.clone()
+                    };
+                    ui.label(description);
+                }
+            });
+        });
+    });
+}
+
+fn draw_hidden(ui: &mut Ui, image: Image, id: egui::Id, settings: &ChatSettings) {
+    if !settings.hide_embeds {
+        ui.add(image);
+        return;
+    }
+
+    let reveal_id = id.with("revealed");
+    let revealed = ui.data(|d| d.get_temp(reveal_id).unwrap_or(false));
+    let response = ui.add(image.sense(egui::Sense::click()));
+    if revealed {
+        return;
+    }
+
+    ui.painter()
+        .rect_filled(response.rect, 4.0, Color32::from_black_alpha(245));
+    ui.painter().text(
+        response.rect.center(),
+        egui::Align2::CENTER_CENTER,
+        "click to reveal",
+        egui::FontId::proportional(14.0),
+        Color32::GRAY,
+    );
+    if response.clicked() {
+        ui.data_mut(|d| d.insert_temp(reveal_id, true));
+    }
+}
+
+fn draw_asset(
+    ui: &mut Ui,
+    asset: &Asset,
+    _id: &str,
+    settings: &ChatSettings,
+    prefs: &AccountPrefs,
+) {
+    let colors = theme::colors(ui.ctx());
     match asset {
         Asset::Emote { src, pattern, .. } => {
             if settings.auto_embed_emotes {
-                if src.is_empty() {
-                    ui.label(RichText::new(pattern).color(Color32::YELLOW));
+                if src.is_empty() || !prefs.auto_embed(src) {
+                    ui.label(RichText::new(pattern).color(colors.asset));
                 } else {
                     ui.add(
                         Image::from_uri(src)
@@ -581,13 +1839,13 @@ fn draw_asset(ui: &mut Ui, asset: &Asset, _id: &str, settings: &ChatSettings) {
                     );
                 }
             } else {
-                ui.label(RichText::new(pattern).color(Color32::YELLOW));
+                ui.label(RichText::new(pattern).color(colors.asset));
             }
         }
         Asset::Sticker { src, pattern, .. } => {
             if settings.auto_embed_stickers {
-                if src.is_empty() {
-                    ui.label(RichText::new(pattern).color(Color32::YELLOW));
+                if src.is_empty() || !prefs.auto_embed(src) {
+                    ui.label(RichText::new(pattern).color(colors.asset));
                 } else {
                     ui.add(
                         Image::from_uri(src)
@@ -596,14 +1854,14 @@ fn draw_asset(ui: &mut Ui, asset: &Asset, _id: &str, settings: &ChatSettings) {
                     );
                 }
             } else {
-                ui.label(RichText::new(format!("[sticker] {}", pattern)).color(Color32::YELLOW));
+                ui.label(RichText::new(format!("[sticker] {}", pattern)).color(colors.asset));
             }
         }
         Asset::Audio { src, pattern, .. } => {
-            if settings.auto_embed_audio && !src.is_empty() {
+            if settings.auto_embed_audio && !src.is_empty() && prefs.trust(src) != Trust::Blocked {
                 ui.hyperlink_to(format!("[audio] {}", pattern), src);
             } else {
-                ui.label(RichText::new(format!("[audio] {}", pattern)).color(Color32::YELLOW));
+                ui.label(RichText::new(format!("[audio] {}", pattern)).color(colors.asset));
             }
         }
         Asset::Command { args, .. } => {
@@ -617,9 +1875,272 @@ fn draw_asset(ui: &mut Ui, asset: &Asset, _id: &str, settings: &ChatSettings) {
                 .collect();
             ui.label(
                 RichText::new(format!("/{}", arg_str.join(" ")))
-                    .color(Color32::from_rgb(100, 150, 255))
+                    .color(colors.command)
                     .monospace(),
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: Vec<MessageFragment>) -> Message {
+        Message {
+            id: Some("1".to_string()),
+            sender_id: Some("me".to_string()),
+            content,
+            timestamp: Utc::now(),
+            message_type: MessageType::CurrentUser,
+            status: MessageStatus::Sent,
+        }
+    }
+
+    fn text(text: &str) -> MessageFragment {
+        MessageFragment::Text(text.to_string())
+    }
+
+    fn wave_assets() -> HashMap<String, Asset> {
+        HashMap::from([(
+            "wave".to_string(),
+            Asset::Emote {
+                id: None,
+                pattern: ":wave:".to_string(),
+                src: String::new(),
+                source: oshatori::AssetSource::Server,
+            },
+        )])
+    }
+
+    #[test]
+    fn editing_keeps_reply_line_and_other_fragments() {
+        let url = MessageFragment::Url("https://example.com".to_string());
+        let original = message(vec![
+            text("> alice: earlier message\nsee "),
+            url.clone(),
+            text(" for more"),
+        ]);
+        assert_eq!(
+            editable_text(&original, &HashMap::new()),
+            "see https://example.com for more"
+        );
+
+        let content = edited_content(
+            &original,
+            "look at https://example.com\nfor more",
+            &HashMap::new(),
+        );
+        assert_eq!(
+            content,
+            vec![
+                text("> alice: earlier message\nlook at "),
+                url.clone(),
+                text("\nfor more"),
+            ]
+        );
+        let (quote, body) = split_reply(&message(content)).unwrap();
+        assert_eq!(quote.name, "alice");
+        assert_eq!(body, vec![text("look at "), url, text("\nfor more")]);
+    }
+
+    #[test]
+    fn editing_drops_fragments_removed_from_the_text() {
+        let original = message(vec![
+            text("a "),
+            MessageFragment::Url("https://example.com".to_string()),
+            text(" b"),
+        ]);
+        assert_eq!(
+            edited_content(&original, "a b", &HashMap::new()),
+            vec![text("a b")]
+        );
+    }
+
+    #[test]
+    fn editing_media_only_message_adds_text() {
+        let emote = MessageFragment::AssetId("wave".to_string());
+        let original = message(vec![emote.clone()]);
+        assert_eq!(editable_text(&original, &wave_assets()), ":wave:");
+        assert_eq!(
+            edited_content(&original, "hi :wave:", &wave_assets()),
+            vec![text("hi "), emote]
+        );
+    }
+
+    #[test]
+    fn editing_plain_message_replaces_text() {
+        let original = message(vec![text("helo")]);
+        assert_eq!(
+            edited_content(&original, "hello", &HashMap::new()),
+            vec![text("hello")]
+        );
+    }
+
+    fn sent(id: &str, sender: &str, secs: i64, content: &str) -> Message {
+        Message {
+            id: Some(id.to_string()),
+            sender_id: Some(sender.to_string()),
+            content: vec![text(content)],
+            timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+            message_type: MessageType::Normal,
+            status: MessageStatus::Sent,
+        }
+    }
+
+    fn users() -> HashMap<String, Profile> {
+        HashMap::from([(
+            "2".to_string(),
+            Profile {
+                id: Some("2".to_string()),
+                username: Some("alice".to_string()),
+                display_name: None,
+                color: None,
+                picture: None,
+            },
+        )])
+    }
+
+    #[test]
+    fn reply_quote_flattens_and_truncates() {
+        assert_eq!(reply_quote("alice", " two\nlines "), "> alice: two lines\n");
+        let long = "x".repeat(60);
+        assert_eq!(
+            reply_quote("alice", &long),
+            format!("> alice: {}...\n", "x".repeat(50))
+        );
+    }
+
+    #[test]
+    fn split_reply_reads_back_reply_quote() {
+        let quoted = format!("{}thanks", reply_quote("alice", &"x".repeat(60)));
+        let (quote, body) = split_reply(&message(vec![text(&quoted)])).unwrap();
+        assert_eq!(quote.name, "alice");
+        assert_eq!(quote.snippet, format!("{}...", "x".repeat(50)));
+        assert_eq!(body, vec![text("thanks")]);
+    }
+
+    #[test]
+    fn split_reply_ignores_other_quote_lines() {
+        let long = format!("> alice: {}\nhi", "x".repeat(60));
+        for content in ["> : snippet\nhi", "> alice:  \nhi", "> no colon\nhi", &long] {
+            assert!(split_reply(&message(vec![text(content)])).is_none());
+        }
+    }
+
+    #[test]
+    fn find_parent_matches_protocol_name_and_snippet() {
+        let users = users();
+        let assets = HashMap::new();
+        let parent = sent("10", "2", 0, "first thing I said");
+        let other = sent("11", "2", 1, "something else");
+        let reply = sent("12", "3", 2, "> alice: first thing\nagreed");
+        let messages = [&parent, &other, &reply];
+
+        let (quote, _) = split_reply(&reply).unwrap();
+        let found = find_parent(&messages, &reply, &quote, &users, &assets);
+        assert_eq!(found.and_then(|m| m.id.as_deref()), Some("10"));
+
+        // Quoting a name nobody has, e.g. a local nickname, finds nothing.
+        let nickname = sent("13", "3", 3, "> Ally: first thing\nagreed");
+        let (quote, _) = split_reply(&nickname).unwrap();
+        assert!(find_parent(&messages, &nickname, &quote, &users, &assets).is_none());
+    }
+
+    #[test]
+    fn find_parent_ignores_later_messages() {
+        let users = users();
+        let reply = sent("12", "3", 0, "> alice: later\nhm");
+        let later = sent("13", "2", 5, "later");
+        let (quote, _) = split_reply(&reply).unwrap();
+        assert!(find_parent(&[&reply, &later], &reply, &quote, &users, &HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn channel_kind_names_each_type() {
+        assert_eq!(channel_kind(&ChannelType::Group), "group");
+        assert_eq!(channel_kind(&ChannelType::Direct), "direct");
+        assert_eq!(channel_kind(&ChannelType::Broadcast), "broadcast");
+    }
+
+    #[test]
+    fn group_window_limits_consecutive_messages() {
+        let settings = |group_window_secs| ChatSettings {
+            group_window_secs,
+            ..ChatSettings::default()
+        };
+        let msg = sent("2", "2", 90, "later");
+        let at = |secs: i64| DateTime::from_timestamp(1_700_000_000 + secs, 0);
+        assert!(in_group_window(at(30), &msg, &settings(60)));
+        assert!(!in_group_window(at(0), &msg, &settings(60)));
+        assert!(!in_group_window(None, &msg, &settings(60)));
+        // Zero turns the window off and groups by sender alone.
+        assert!(in_group_window(at(0), &msg, &settings(0)));
+    }
+
+    fn rendered(layout: MessageLayout, msg: &Message) -> Vec<String> {
+        let settings = ChatSettings {
+            layout,
+            time_format: crate::state::TimeFormat::H24,
+            show_seconds: true,
+            ..ChatSettings::default()
+        };
+        let users = users();
+        let cx = MessageContext {
+            users: &users,
+            assets: &HashMap::new(),
+            settings: &settings,
+            prefs: &AccountPrefs::default(),
+            channel_id: None,
+            current_user_id: None,
+            can_edit: false,
+            messages: &[],
+            scroll_to: None,
+            highlight: None,
+        };
+
+        let ctx = egui::Context::default();
+        fonts::install_defaults(&ctx);
+        let output = ctx.run(egui::RawInput::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                draw_message(ui, msg, false, &cx);
+            });
+        });
+        output
+            .shapes
+            .into_iter()
+            .filter_map(|clipped| match clipped.shape {
+                egui::Shape::Text(text) => Some(text.galley.text().to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layouts_place_time_and_nick_differently() {
+        use chrono::TimeZone;
+        let mut msg = sent("1", "2", 0, "hello");
+        msg.timestamp = Local
+            .with_ymd_and_hms(2024, 3, 9, 13, 5, 9)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let compact = rendered(MessageLayout::Compact, &msg);
+        assert!(compact.contains(&"[13:05:09]".to_string()));
+        assert!(compact.contains(&"<alice>".to_string()));
+
+        let irc = rendered(MessageLayout::Irc, &msg);
+        assert!(irc.contains(&"13:05:09".to_string()));
+        assert!(irc.contains(&"alice".to_string()));
+        assert!(irc.contains(&"│".to_string()));
+
+        let cozy = rendered(MessageLayout::Cozy, &msg);
+        assert!(cozy.contains(&"alice".to_string()));
+        assert!(!cozy.contains(&"<alice>".to_string()));
+        assert!(!cozy.contains(&"│".to_string()));
+
+        for texts in [compact, irc, cozy] {
+            assert!(texts.contains(&"hello".to_string()));
+        }
+    }
+}
@@ -1,13 +1,21 @@
 mod accounts;
 mod channels;
 mod chat;
+mod notifications;
+mod palette;
+mod popouts;
 mod popups;
 mod settings;
+mod switcher;
 mod users;
 
 pub use accounts::draw_accounts;
 pub use channels::draw_channels;
 pub use chat::draw_chat;
+pub use notifications::draw_notifications;
+pub use palette::{draw_palette, PaletteState};
+pub use popouts::draw_popouts;
 pub use popups::draw_popups;
 pub use settings::draw_settings;
+pub use switcher::draw_switcher;
 pub use users::draw_users;
@@ -0,0 +1,147 @@
+use crate::app::ChatClient;
+use crate::state::NotificationKind;
+use crate::theme;
+use crate::utils::format_timestamp;
+use eframe::egui::{self, Color32, Image, RichText, ScrollArea};
+
+const PREVIEW_CHARS: usize = 120;
+
+pub fn draw_notifications(client: &mut ChatClient, ctx: &egui::Context) {
+    if !client.show_notifications {
+        return;
+    }
+
+    let (notifications, accounts, account_prefs) = {
+        let cache = client.cache.lock().unwrap();
+        (
+            cache.notifications.clone(),
+            cache.accounts.clone(),
+            cache.account_prefs.clone(),
+        )
+    };
+    let colors = theme::colors(ctx);
+
+    let mut open = true;
+    let mut selected = None;
+    let mut mark_all = false;
+    let mut clear = false;
+    egui::Window::new("Notifications")
+        .open(&mut open)
+        .collapsible(false)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                mark_all = ui.button("Mark all read").clicked();
+                clear = ui.button("Clear").clicked();
+            });
+            ui.separator();
+
+            if notifications.is_empty() {
+                ui.label(RichText::new("Nothing yet").color(Color32::GRAY));
+                return;
+            }
+
+            ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for notification in notifications.iter().rev() {
+                    let account = accounts
+                        .get(notification.account_index)
+                        .and_then(|a| a.private_profile.as_ref())
+                        .and_then(|p| p.username.as_deref())
+                        .unwrap_or("Unknown");
+                    let channel = match notification.channel_id.as_str() {
+                        "" => "General",
+                        id => id,
+                    };
+                    let kind = match notification.kind {
+                        NotificationKind::Direct => "direct message",
+                        NotificationKind::Mention => "mention",
+                        NotificationKind::Message => "message",
+                    };
+
+                    let response = ui
+                        .scope_builder(egui::UiBuilder::new().sense(egui::Sense::click()), |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    RichText::new(format_timestamp(
+                                        notification.timestamp,
+                                        &client.chat_settings,
+                                    ))
+                                    .small()
+                                    .color(Color32::GRAY),
+                                );
+                                ui.label(
+                                    RichText::new(format!("#{} · {} · {}", channel, account, kind))
+                                        .small()
+                                        .color(Color32::GRAY),
+                                );
+                            });
+                            let mut sender = RichText::new(&notification.sender).strong();
+                            if notification.kind != NotificationKind::Message {
+                                sender = sender.color(colors.mention);
+                            }
+                            let mut text: String =
+                                notification.text.chars().take(PREVIEW_CHARS).collect();
+                            if notification.text.chars().count() > PREVIEW_CHARS {
+                                text.push('…');
+                            }
+                            ui.horizontal_wrapped(|ui| {
+                                if let Some(picture) =
+                                    notification.sender_picture.as_ref().filter(|p| {
+                                        account_prefs
+                                            .get(notification.account_index)
+                                            .is_some_and(|prefs| prefs.auto_embed(p))
+                                    })
+                                {
+                                    ui.add(
+                                        Image::from_uri(picture)
+                                            .fit_to_exact_size(egui::Vec2::new(20.0, 20.0))
+                                            .rounding(egui::Rounding::same(2.0)),
+                                    );
+                                }
+                                ui.label(sender);
+                                if notification.read {
+                                    ui.label(RichText::new(text).color(Color32::GRAY));
+                                } else {
+                                    ui.label(text);
+                                }
+                            });
+                        })
+                        .response;
+                    if response.hovered() {
+                        ui.painter()
+                            .rect_filled(response.rect, 2.0, colors.highlight);
+                    }
+                    if response.clicked() {
+                        selected = Some(notification.id);
+                    }
+                    ui.separator();
+                }
+            });
+        });
+
+    let mut cache = client.cache.lock().unwrap();
+    if clear {
+        cache.notifications.clear();
+    }
+    let mut target = None;
+    for notification in &mut cache.notifications {
+        if mark_all || selected == Some(notification.id) {
+            notification.read = true;
+        }
+        if selected == Some(notification.id) {
+            target = Some((
+                notification.connection_id.clone(),
+                notification.channel_id.clone(),
+            ));
+        }
+    }
+    drop(cache);
+
+    if let Some((connection_id, channel_id)) = target {
+        client.set_active_connection(connection_id);
+        client.sync_selection(channel_id);
+    }
+    if !open {
+        client.show_notifications = false;
+    }
+}
@@ -0,0 +1,185 @@
+use crate::app::ChatClient;
+use crate::keys::Action;
+use crate::utils::fuzzy_score;
+use eframe::egui::{self, Color32, RichText, TextEdit};
+
+pub(super) const MAX_RESULTS: usize = 12;
+
+#[derive(Default)]
+pub struct PaletteState {
+    pub query: String,
+    pub selected: usize,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Action(Action),
+    Channel {
+        connection_id: String,
+        channel_id: String,
+    },
+    Account(usize),
+}
+
+fn entries(client: &ChatClient, ctx: &egui::Context) -> Vec<(Entry, String, String)> {
+    let mut entries: Vec<(Entry, String, String)> = Action::ALL
+        .iter()
+        .map(|action| {
+            let hint = client
+                .keybindings
+                .shortcut(*action)
+                .map(|s| ctx.format_shortcut(s))
+                .unwrap_or_default();
+            (Entry::Action(*action), action.label().to_string(), hint)
+        })
+        .collect();
+
+    let cache = client.cache.lock().unwrap();
+    let account_name = |index: usize| {
+        cache
+            .accounts
+            .get(index)
+            .and_then(|a| a.private_profile.as_ref())
+            .and_then(|p| p.username.clone())
+            .unwrap_or_else(|| "Unknown".to_string())
+    };
+
+    let mut connections: Vec<_> = cache.connections.values().collect();
+    connections.sort_by(|a, b| a.connection_id.cmp(&b.connection_id));
+    for conn in connections {
+        for channel_id in &conn.channels {
+            let display = if channel_id.is_empty() {
+                "General"
+            } else {
+                channel_id
+            };
+            entries.push((
+                Entry::Channel {
+                    connection_id: conn.connection_id.clone(),
+                    channel_id: channel_id.clone(),
+                },
+                format!("#{}", display),
+                account_name(conn.account_index),
+            ));
+        }
+    }
+
+    for (index, account) in cache.accounts.iter().enumerate() {
+        entries.push((
+            Entry::Account(index),
+            format!("Account: {}", account_name(index)),
+            account.protocol_name.clone(),
+        ));
+    }
+    entries
+}
+
+pub(super) fn draw_picker(
+    ctx: &egui::Context,
+    title: &str,
+    hint_text: &str,
+    state: &mut PaletteState,
+    rows: &[(RichText, &str)],
+) -> (bool, Option<usize>) {
+    state.selected = state.selected.min(rows.len().saturating_sub(1));
+    let mut open = true;
+    let mut chosen = None;
+    egui::Window::new(title)
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+        .fixed_size([420.0, 0.0])
+        .show(ctx, |ui| {
+            let response = ui.add(
+                TextEdit::singleline(&mut state.query)
+                    .desired_width(f32::INFINITY)
+                    .hint_text(hint_text),
+            );
+            response.request_focus();
+            if response.changed() {
+                state.selected = 0;
+            }
+
+            ui.input(|i| {
+                if i.key_pressed(egui::Key::ArrowDown) {
+                    state.selected = (state.selected + 1).min(rows.len().saturating_sub(1));
+                }
+                if i.key_pressed(egui::Key::ArrowUp) {
+                    state.selected = state.selected.saturating_sub(1);
+                }
+                if i.key_pressed(egui::Key::Escape) {
+                    open = false;
+                }
+                if i.key_pressed(egui::Key::Enter) && state.selected < rows.len() {
+                    chosen = Some(state.selected);
+                }
+            });
+
+            ui.separator();
+            if rows.is_empty() {
+                ui.label(RichText::new("No matches").color(Color32::GRAY));
+            }
+            for (i, (label, hint)) in rows.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .selectable_label(i == state.selected, label.clone())
+                        .clicked()
+                    {
+                        chosen = Some(i);
+                    }
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(RichText::new(*hint).small().color(Color32::GRAY));
+                    });
+                });
+            }
+        });
+    (open, chosen)
+}
+
+pub fn draw_palette(client: &mut ChatClient, ctx: &egui::Context) {
+    let Some(mut state) = client.palette.take() else {
+        return;
+    };
+
+    let mut ranked: Vec<(i32, (Entry, String, String))> = entries(client, ctx)
+        .into_iter()
+        .filter_map(|entry| Some((fuzzy_score(&state.query, &entry.1)?, entry)))
+        .collect();
+    ranked.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    ranked.truncate(MAX_RESULTS);
+
+    let rows: Vec<(RichText, &str)> = ranked
+        .iter()
+        .map(|(_, (_, label, hint))| (RichText::new(label), hint.as_str()))
+        .collect();
+    let (open, chosen) = draw_picker(
+        ctx,
+        "Command palette",
+        "Type a command, channel or account...",
+        &mut state,
+        &rows,
+    );
+    let chosen = chosen.map(|i| ranked[i].1 .0.clone());
+
+    match chosen {
+        Some(Entry::Action(Action::CommandPalette)) => client.palette = Some(state),
+        Some(Entry::Action(action)) => client.run_action(ctx, action),
+        Some(Entry::Channel {
+            connection_id,
+            channel_id,
+        }) => {
+            client.set_active_connection(connection_id);
+            client.sync_selection(channel_id);
+        }
+        Some(Entry::Account(index)) => {
+            let connection_id = client.account_to_conn.lock().unwrap().get(&index).cloned();
+            match connection_id {
+                Some(id) => client.set_active_connection(id),
+                None => client.selected_account = Some(index),
+            }
+        }
+        None if open => client.palette = Some(state),
+        None => {}
+    }
+}
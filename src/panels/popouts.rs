@@ -0,0 +1,80 @@
+use super::chat::draw_channel_pane;
+use crate::app::ChatClient;
+use eframe::egui;
+
+pub fn draw_popouts(client: &mut ChatClient, ctx: &egui::Context) {
+    let mut popouts = std::mem::take(&mut client.session.popouts);
+
+    popouts.retain_mut(|popout| {
+        let id = egui::Id::new(("popout", &popout.account, &popout.channel_id));
+        // Windows for accounts that have since been deleted are dropped.
+        let Some(account_index) = client.find_account(&popout.account) else {
+            client.pane_states.remove(&id);
+            return false;
+        };
+        let account = client
+            .cache
+            .lock()
+            .unwrap()
+            .accounts
+            .get(account_index)
+            .and_then(|a| a.private_profile.as_ref())
+            .and_then(|p| p.username.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let title = format!(
+            "#{} - {}",
+            match popout.channel_id.as_str() {
+                "" => "General",
+                id => id,
+            },
+            account
+        );
+
+        let mut builder = egui::ViewportBuilder::default()
+            .with_title(&title)
+            .with_inner_size(popout.size);
+        if let Some(position) = popout.position {
+            builder = builder.with_position(position);
+        }
+
+        let channel_id = popout.channel_id.clone();
+        let open = ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of(id),
+            builder,
+            |ctx, class| {
+                if class == egui::ViewportClass::Embedded {
+                    let mut open = true;
+                    egui::Window::new(&title)
+                        .id(id)
+                        .open(&mut open)
+                        .default_size(popout.size)
+                        .show(ctx, |ui| {
+                            draw_channel_pane(client, ui, account_index, &channel_id, id, false);
+                        });
+                    return open;
+                }
+
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    draw_channel_pane(client, ui, account_index, &channel_id, id, false);
+                });
+                ctx.input(|i| {
+                    let viewport = i.viewport();
+                    if let Some(rect) = viewport.inner_rect {
+                        popout.size = rect.size().into();
+                    }
+                    if let Some(rect) = viewport.outer_rect {
+                        popout.position = Some(rect.min.into());
+                    }
+                    !viewport.close_requested()
+                })
+            },
+        );
+        if !open {
+            client.pane_states.remove(&id);
+        }
+        open
+    });
+
+    popouts.append(&mut client.session.popouts);
+    client.session.popouts = popouts;
+}
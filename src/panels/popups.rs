@@ -1,10 +1,378 @@
 use crate::app::ChatClient;
-use crate::utils::save_accounts;
-use eframe::egui::{self, TextEdit, Ui};
-use oshatori::{Account, AuthField, FieldValue};
+use crate::state::{ChannelCommand, NotifyLevel};
+use crate::utils::{format_timestamp, message_text, save_account_prefs, save_accounts, user_color};
+use eframe::egui::{self, Image, RichText, ScrollArea, TextEdit, Ui};
+use oshatori::{Account, AuthField, FieldValue, Message};
 
 pub fn draw_popups(client: &mut ChatClient, ctx: &egui::Context) {
     draw_account_popup(client, ctx);
+    draw_profile_popup(client, ctx);
+    draw_source_popup(client, ctx);
+    draw_channel_popup(client, ctx);
+    draw_channel_browser(client, ctx);
+}
+
+fn draw_channel_popup(client: &mut ChatClient, ctx: &egui::Context) {
+    if !client.show_channel_popup {
+        return;
+    }
+
+    // Only sockchat's text commands carry a channel password; the events
+    // other protocols use have nowhere to put one.
+    let passwords = client
+        .active_connection()
+        .is_some_and(|conn| client.capabilities(conn.account_index).text_commands);
+
+    let mut open = true;
+    let mut command = None;
+    egui::Window::new("Join or create channel")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("channel_popup_grid").show(ui, |ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut client.channel_name);
+                ui.end_row();
+                if passwords {
+                    ui.label("Password:");
+                    ui.add(
+                        TextEdit::singleline(&mut client.channel_password)
+                            .password(true)
+                            .hint_text("optional"),
+                    );
+                    ui.end_row();
+                }
+            });
+
+            let name = client.channel_name.trim().to_string();
+            let password = Some(client.channel_password.trim().to_string())
+                .filter(|p| passwords && !p.is_empty());
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(!name.is_empty(), |ui| {
+                    if ui.button("Join").clicked() {
+                        command = Some(ChannelCommand::Join {
+                            name: name.clone(),
+                            password: password.clone(),
+                        });
+                    }
+                    if ui.button("Create").clicked() {
+                        command = Some(ChannelCommand::Create {
+                            name: name.clone(),
+                            password: password.clone(),
+                        });
+                    }
+                });
+            });
+        });
+
+    if let Some(command) = command {
+        client.run_channel_command(command);
+        open = false;
+    }
+    if !open {
+        client.show_channel_popup = false;
+        client.channel_name.clear();
+        client.channel_password.clear();
+    }
+}
+
+fn draw_channel_browser(client: &mut ChatClient, ctx: &egui::Context) {
+    if !client.show_channel_browser {
+        return;
+    }
+    let Some(conn) = client.active_connection() else {
+        client.show_channel_browser = false;
+        return;
+    };
+
+    let mut channels: Vec<(&String, usize)> = conn
+        .channels
+        .iter()
+        .map(|id| (id, conn.channel_users.get(id).map(|u| u.len()).unwrap_or(0)))
+        .collect();
+    channels.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let current = conn
+        .current_channel
+        .as_ref()
+        .map(|ch| ch.channel.id.clone());
+
+    let mut open = true;
+    egui::Window::new("Browse channels")
+        .open(&mut open)
+        .collapsible(false)
+        .default_size([300.0, 350.0])
+        .show(ctx, |ui| {
+            if channels.is_empty() {
+                ui.label("The server has not listed any channels");
+                return;
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("channel_browser_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (id, users) in &channels {
+                            ui.label(if id.is_empty() {
+                                "General"
+                            } else {
+                                id.as_str()
+                            });
+                            ui.label(
+                                RichText::new(format!("{} users", users))
+                                    .small()
+                                    .color(egui::Color32::GRAY),
+                            );
+                            if current.as_ref() == Some(*id) {
+                                ui.label(RichText::new("joined").small());
+                            } else if ui.button("Join").clicked() {
+                                client.run_channel_command(ChannelCommand::Join {
+                                    name: id.to_string(),
+                                    password: None,
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+    if !open {
+        client.show_channel_browser = false;
+    }
+}
+
+fn draw_profile_popup(client: &mut ChatClient, ctx: &egui::Context) {
+    let Some(user_id) = client.profile_user.clone() else {
+        return;
+    };
+    let Some(conn) = client.active_connection() else {
+        client.profile_user = None;
+        return;
+    };
+    let prefs = client.account_prefs(conn.account_index);
+    let profile = client.find_user(&user_id);
+    let name = prefs.display_name(&user_id, profile.as_ref());
+    let username = profile
+        .as_ref()
+        .and_then(|p| p.username.clone())
+        .unwrap_or_else(|| name.clone());
+
+    let mut shared: Vec<String> = conn
+        .channel_users
+        .iter()
+        .filter(|(_, users)| users.contains(&user_id))
+        .map(|(id, _)| id.clone())
+        .collect();
+    shared.sort();
+
+    let recent: Vec<Message> = conn
+        .current_channel
+        .as_ref()
+        .map(|ch| {
+            ch.messages
+                .iter()
+                .rev()
+                .filter(|m| m.sender_id.as_ref() == Some(&user_id))
+                .take(5)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut open = true;
+    egui::Window::new("Profile")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(pic) = profile
+                    .as_ref()
+                    .and_then(|p| p.picture.as_ref())
+                    .filter(|p| prefs.auto_embed(p))
+                {
+                    ui.add(
+                        Image::from_uri(pic)
+                            .fit_to_exact_size(egui::Vec2::new(96.0, 96.0))
+                            .rounding(egui::Rounding::same(4.0)),
+                    );
+                }
+                ui.vertical(|ui| {
+                    let color = user_color(
+                        &user_id,
+                        profile.as_ref(),
+                        &prefs,
+                        &client.chat_settings,
+                        ui.visuals().panel_fill,
+                    );
+                    let mut text = RichText::new(&name).heading();
+                    if let Some(color) = color {
+                        text = text.color(color);
+                    }
+                    ui.label(text);
+                    ui.label(&username);
+                    ui.label(RichText::new(format!("id: {}", user_id)).small());
+                    if let Some(color) = color {
+                        ui.horizontal(|ui| {
+                            let (rect, _) = ui.allocate_exact_size(
+                                egui::Vec2::new(12.0, 12.0),
+                                egui::Sense::hover(),
+                            );
+                            ui.painter().rect_filled(rect, 2.0, color);
+                            ui.label(
+                                RichText::new(format!(
+                                    "#{:02x}{:02x}{:02x}",
+                                    color.r(),
+                                    color.g(),
+                                    color.b()
+                                ))
+                                .small(),
+                            );
+                        });
+                    }
+                });
+            });
+
+            ui.separator();
+            ui.label(RichText::new("Shared channels").strong());
+            if shared.is_empty() {
+                ui.label(RichText::new("None").color(egui::Color32::GRAY));
+            } else {
+                ui.horizontal_wrapped(|ui| {
+                    for channel_id in &shared {
+                        let display = if channel_id.is_empty() {
+                            "General"
+                        } else {
+                            channel_id
+                        };
+                        if ui.link(display).clicked() {
+                            client.sync_selection(channel_id.clone());
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.label(RichText::new("Recent messages").strong());
+            if recent.is_empty() {
+                ui.label(RichText::new("None").color(egui::Color32::GRAY));
+            }
+            for msg in &recent {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(
+                        RichText::new(format_timestamp(msg.timestamp, &client.chat_settings))
+                            .small()
+                            .color(egui::Color32::GRAY),
+                    );
+                    ui.label(message_text(&msg.content, &conn.assets));
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Mention").clicked() {
+                    client.new_message.push_str(&format!("@{} ", username));
+                    client.profile_user = None;
+                }
+                if ui.button("Message").clicked() {
+                    client.open_direct_message(ctx, &user_id, &username);
+                    client.profile_user = None;
+                }
+                let ignored = prefs.ignored_users.contains(&user_id);
+                if ui
+                    .button(if ignored { "Unignore" } else { "Ignore" })
+                    .clicked()
+                {
+                    client.update_account_prefs(conn.account_index, |p| {
+                        if ignored {
+                            p.ignored_users.retain(|id| id != &user_id);
+                        } else {
+                            p.ignored_users.push(user_id.clone());
+                        }
+                    });
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let draft_id = egui::Id::new(("nickname_draft", &user_id));
+                let mut draft = ui.data(|d| {
+                    d.get_temp::<String>(draft_id).unwrap_or_else(|| {
+                        prefs.nicknames.get(&user_id).cloned().unwrap_or_default()
+                    })
+                });
+                ui.label("Nickname:");
+                ui.add(TextEdit::singleline(&mut draft).desired_width(120.0));
+                if ui.button("Set").clicked() {
+                    let nickname = draft.trim().to_string();
+                    client.update_account_prefs(conn.account_index, |p| {
+                        if nickname.is_empty() {
+                            p.nicknames.remove(&user_id);
+                        } else {
+                            p.nicknames.insert(user_id.clone(), nickname);
+                        }
+                    });
+                }
+                ui.data_mut(|d| d.insert_temp(draft_id, draft));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Color:");
+                let mut color = prefs
+                    .colors
+                    .get(&user_id)
+                    .copied()
+                    .or_else(|| profile.as_ref().and_then(|p| p.color))
+                    .unwrap_or([255, 255, 255, 255]);
+                if ui
+                    .color_edit_button_srgba_unmultiplied(&mut color)
+                    .changed()
+                {
+                    client.update_account_prefs(conn.account_index, |p| {
+                        p.colors.insert(user_id.clone(), color);
+                    });
+                }
+                if prefs.colors.contains_key(&user_id) && ui.button("Reset").clicked() {
+                    client.update_account_prefs(conn.account_index, |p| {
+                        p.colors.remove(&user_id);
+                    });
+                }
+            });
+        });
+
+    if !open {
+        client.profile_user = None;
+    }
+}
+
+fn draw_source_popup(client: &mut ChatClient, ctx: &egui::Context) {
+    let Some(message) = &client.message_source else {
+        return;
+    };
+    let mut source = serde_json::to_string_pretty(message).unwrap_or_default();
+
+    let mut open = true;
+    egui::Window::new("Message source")
+        .open(&mut open)
+        .resizable(true)
+        .default_size([420.0, 320.0])
+        .show(ctx, |ui| {
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(source.clone());
+            }
+            ui.separator();
+            ScrollArea::vertical().show(ui, |ui| {
+                ui.add(
+                    TextEdit::multiline(&mut source)
+                        .code_editor()
+                        .desired_width(f32::INFINITY)
+                        .interactive(false),
+                );
+            });
+        });
+
+    if !open {
+        client.message_source = None;
+    }
 }
 
 fn draw_account_popup(client: &mut ChatClient, ctx: &egui::Context) {
@@ -82,6 +450,51 @@ fn draw_account_popup(client: &mut ChatClient, ctx: &egui::Context) {
                 client.temp_auth = temp;
             }
 
+            ui.separator();
+            ui.label("Media safety:");
+            ui.checkbox(
+                &mut client.temp_prefs.trusted_only,
+                "Only auto-embed from trusted domains",
+            );
+            domain_list_ui(
+                ui,
+                "Trusted domains:",
+                &mut client.temp_prefs.trusted_domains,
+            );
+            domain_list_ui(
+                ui,
+                "Blocked domains:",
+                &mut client.temp_prefs.blocked_domains,
+            );
+            ui.checkbox(
+                &mut client.temp_prefs.link_previews,
+                "Show link previews for trusted domains",
+            );
+            if client.temp_prefs.link_previews {
+                ui.checkbox(
+                    &mut client.temp_prefs.preview_untrusted,
+                    "Also preview links to untrusted domains",
+                );
+                domain_list_ui(
+                    ui,
+                    "No previews for domains:",
+                    &mut client.temp_prefs.no_preview_domains,
+                );
+            }
+
+            ui.separator();
+            egui::ComboBox::from_label("Notify for")
+                .selected_text(client.temp_prefs.notify_level.label())
+                .show_ui(ui, |ui| {
+                    for level in NotifyLevel::ALL {
+                        ui.selectable_value(
+                            &mut client.temp_prefs.notify_level,
+                            level,
+                            level.label(),
+                        );
+                    }
+                });
+
             ui.separator();
             ui.horizontal(|ui| {
                 if ui.button("Save").clicked() {
@@ -93,14 +506,30 @@ fn draw_account_popup(client: &mut ChatClient, ctx: &egui::Context) {
                             autoconnect: false,
                         };
 
-                        {
-                            let mut cache = client.cache.lock().unwrap();
-                            if let Some(edit_idx) = client.editing_account {
+                        if let Some(edit_idx) = client.editing_account {
+                            {
+                                let mut cache = client.cache.lock().unwrap();
                                 cache.accounts[edit_idx] = account;
-                            } else {
-                                cache.accounts.push(account);
+                                save_accounts(&cache.accounts);
                             }
+                            // Other prefs may have changed while the popup was
+                            // open, so only write back what it edits.
+                            let edited = client.temp_prefs.clone();
+                            client.update_account_prefs(edit_idx, |p| {
+                                p.trusted_only = edited.trusted_only;
+                                p.trusted_domains = edited.trusted_domains;
+                                p.blocked_domains = edited.blocked_domains;
+                                p.link_previews = edited.link_previews;
+                                p.preview_untrusted = edited.preview_untrusted;
+                                p.no_preview_domains = edited.no_preview_domains;
+                                p.notify_level = edited.notify_level;
+                            });
+                        } else {
+                            let mut cache = client.cache.lock().unwrap();
+                            cache.accounts.push(account);
+                            cache.account_prefs.push(client.temp_prefs.clone());
                             save_accounts(&cache.accounts);
+                            save_account_prefs(&cache.account_prefs);
                         }
 
                         client.show_account_popup = false;
@@ -114,7 +543,40 @@ fn draw_account_popup(client: &mut ChatClient, ctx: &egui::Context) {
         });
 }
 
-fn auth_ui(ui: &mut Ui, fields: &mut Vec<AuthField>) {
+fn domain_list_ui(ui: &mut Ui, label: &str, domains: &mut Vec<String>) {
+    list_ui(ui, label, "one domain per line", domains);
+}
+
+// The text being typed is kept as a draft in egui memory and only parsed back
+// into `items` once the field loses focus, so blank lines survive while editing.
+pub(super) fn list_ui(ui: &mut Ui, label: &str, hint: &str, items: &mut Vec<String>) {
+    ui.label(label);
+    let id = ui.make_persistent_id(label);
+    let mut text = ui
+        .data_mut(|d| d.get_temp::<String>(id))
+        .unwrap_or_else(|| items.join("\n"));
+    let response = ui.add(
+        TextEdit::multiline(&mut text)
+            .id(id)
+            .desired_rows(3)
+            .hint_text(hint),
+    );
+
+    if response.has_focus() {
+        ui.data_mut(|d| d.insert_temp(id, text));
+        return;
+    }
+    ui.data_mut(|d| d.remove::<String>(id));
+    if response.lost_focus() {
+        *items = text
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+    }
+}
+
+fn auth_ui(ui: &mut Ui, fields: &mut [AuthField]) {
     for field in fields.iter_mut() {
         ui.horizontal(|ui| {
             let label = field.display.as_ref().unwrap_or(&field.name);
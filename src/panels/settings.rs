@@ -1,40 +1,358 @@
+use super::popups::list_ui;
 use crate::app::ChatClient;
-use eframe::egui;
+use crate::fonts::{detect_fallbacks, font_name};
+use crate::media::format_size;
+use crate::state::{MessageLayout, NotificationPrivacy, TimeFormat};
+use crate::theme::themes_dir;
+use crate::utils::{contrast_ratio, readable_color};
+use eframe::egui::{self, Color32, RichText, ScrollArea};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+const MIB: u64 = 1024 * 1024;
+const PREVIEW_COLORS: [[u8; 3]; 4] = [[20, 30, 140], [30, 30, 30], [120, 40, 160], [240, 200, 60]];
+
+fn font_picker(ui: &mut egui::Ui, label: &str, font: &mut Option<String>, fonts: &[PathBuf]) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", label));
+        egui::ComboBox::from_id_salt(label)
+            .width(150.0)
+            .selected_text(
+                font.as_ref()
+                    .map(|f| font_name(Path::new(f)))
+                    .unwrap_or_else(|| "Default".to_string()),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(font, None, "Default");
+                for path in fonts {
+                    let path = path.display().to_string();
+                    let name = font_name(Path::new(&path));
+                    ui.selectable_value(font, Some(path), name);
+                }
+            });
+    });
+}
+
 pub fn draw_settings(client: &mut ChatClient, ctx: &egui::Context) {
     if !client.panels.settings {
         return;
     }
 
-    egui::SidePanel::right("settings")
+    let response = egui::SidePanel::right("settings")
         .resizable(true)
-        .default_width(220.0)
+        .default_width(client.session.panel_width("settings", 220.0))
         .show(ctx, |ui| {
             ui.heading("Settings");
+            let before = client.chat_settings.clone();
 
-            ui.separator();
-            ui.label("Update interval (ms):");
-            let mut interval_ms = client.update_interval.as_millis() as u64;
-            if ui
-                .add(egui::Slider::new(&mut interval_ms, 100..=2000))
-                .changed()
-            {
-                client.update_interval = Duration::from_millis(interval_ms);
-            }
+            ScrollArea::vertical().show(ui, |ui| {
+                ui.separator();
+                ui.label("Theme:");
+                egui::ComboBox::from_id_salt("theme")
+                    .selected_text(client.chat_settings.theme.clone())
+                    .show_ui(ui, |ui| {
+                        for name in client.themes.names() {
+                            ui.selectable_value(
+                                &mut client.chat_settings.theme,
+                                name.clone(),
+                                name,
+                            );
+                        }
+                    });
+                ui.label(
+                    RichText::new(format!("Custom themes: {}", themes_dir().display()))
+                        .small()
+                        .color(Color32::GRAY),
+                );
+
+                ui.separator();
+                ui.label("Fonts:");
+                ui.label("UI scale:");
+                ui.add(
+                    egui::Slider::new(&mut client.chat_settings.ui_scale, 0.5..=3.0).step_by(0.05),
+                );
+                ui.label("Chat font size:");
+                ui.add(
+                    egui::Slider::new(&mut client.chat_settings.chat_font_size, 8.0..=32.0)
+                        .suffix(" pt"),
+                );
+                let system_fonts = client.fonts.system.clone();
+                let fonts = &mut client.chat_settings.fonts;
+                font_picker(ui, "UI font", &mut fonts.ui, &system_fonts);
+                font_picker(ui, "Chat font", &mut fonts.chat, &system_fonts);
+                font_picker(ui, "Monospace font", &mut fonts.monospace, &system_fonts);
+                ui.label("Fallback fonts:");
+                if fonts.fallbacks.is_empty() {
+                    ui.label(
+                        RichText::new("Detected automatically")
+                            .small()
+                            .color(Color32::GRAY),
+                    );
+                }
+                let mut change = None;
+                for (i, path) in fonts.fallbacks.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("⬆").clicked() && i > 0 {
+                            change = Some((i, Some(i - 1)));
+                        }
+                        if ui.small_button("⬇").clicked() {
+                            change = Some((i, Some(i + 1)));
+                        }
+                        if ui.small_button("✕").clicked() {
+                            change = Some((i, None));
+                        }
+                        ui.label(font_name(Path::new(path)));
+                    });
+                }
+                match change {
+                    Some((i, Some(j))) if j < fonts.fallbacks.len() => fonts.fallbacks.swap(i, j),
+                    Some((i, None)) => {
+                        fonts.fallbacks.remove(i);
+                    }
+                    _ => {}
+                }
+                let mut added = None;
+                font_picker(ui, "Add fallback", &mut added, &system_fonts);
+                if let Some(path) = added {
+                    if fonts.fallbacks.is_empty() {
+                        fonts.fallbacks = detect_fallbacks(&system_fonts);
+                    }
+                    if !fonts.fallbacks.contains(&path) {
+                        fonts.fallbacks.push(path);
+                    }
+                }
+
+                ui.separator();
+                ui.label("Update interval (ms):");
+                let mut interval_ms = client.update_interval.as_millis() as u64;
+                if ui
+                    .add(egui::Slider::new(&mut interval_ms, 100..=2000))
+                    .changed()
+                {
+                    client.update_interval = Duration::from_millis(interval_ms);
+                }
+
+                ui.separator();
+                ui.checkbox(&mut client.chat_settings.autoscroll, "Auto-scroll chat");
+                ui.checkbox(
+                    &mut client.chat_settings.show_deleted_messages,
+                    "Show deleted messages",
+                );
+
+                ui.checkbox(
+                    &mut client.chat_settings.auto_user_colors,
+                    "Generate colors for users without one",
+                );
+                ui.checkbox(
+                    &mut client.chat_settings.readable_colors,
+                    "Adjust user colors for readability",
+                );
+                ui.horizontal_wrapped(|ui| {
+                    let background = ui.visuals().panel_fill;
+                    for color in PREVIEW_COLORS {
+                        let color = Color32::from_rgb(color[0], color[1], color[2]);
+                        let shown = if client.chat_settings.readable_colors {
+                            readable_color(color, background)
+                        } else {
+                            color
+                        };
+                        ui.label(RichText::new("Username").color(shown).strong())
+                            .on_hover_text(format!(
+                                "contrast {:.1}:1",
+                                contrast_ratio(shown, background)
+                            ));
+                    }
+                });
+                ui.checkbox(
+                    &mut client.chat_settings.collapse_ignored,
+                    "Collapse ignored messages",
+                );
 
-            ui.separator();
-            ui.checkbox(&mut client.chat_settings.autoscroll, "Auto-scroll chat");
+                if let Some(account_index) = client.active_connection().map(|c| c.account_index) {
+                    ui.separator();
+                    ui.label("Ignore and mute (this account):");
+                    let mut prefs = client.account_prefs(account_index);
+                    let prefs_before = prefs.clone();
+                    list_ui(
+                        ui,
+                        "Ignored user ids:",
+                        "one id per line",
+                        &mut prefs.ignored_users,
+                    );
+                    list_ui(
+                        ui,
+                        "Ignored name patterns:",
+                        "e.g. spam*bot",
+                        &mut prefs.ignored_patterns,
+                    );
+                    list_ui(
+                        ui,
+                        "Muted channels:",
+                        "one channel per line",
+                        &mut prefs.muted_channels,
+                    );
+                    if prefs != prefs_before {
+                        client.update_account_prefs(account_index, |p| {
+                            p.ignored_users = prefs.ignored_users;
+                            p.ignored_patterns = prefs.ignored_patterns;
+                            p.muted_channels = prefs.muted_channels;
+                        });
+                    }
+                }
 
-            ui.separator();
-            ui.label("Auto-embed media:");
-            ui.checkbox(&mut client.chat_settings.auto_embed_images, "images");
-            ui.checkbox(&mut client.chat_settings.auto_embed_videos, "videos");
-            ui.checkbox(&mut client.chat_settings.auto_embed_audio, "audio");
+                ui.separator();
+                ui.label("Message layout:");
+                egui::ComboBox::from_id_salt("message_layout")
+                    .selected_text(match client.chat_settings.layout {
+                        MessageLayout::Compact => "Compact",
+                        MessageLayout::Cozy => "Cozy",
+                        MessageLayout::Irc => "IRC",
+                    })
+                    .show_ui(ui, |ui| {
+                        let layout = &mut client.chat_settings.layout;
+                        ui.selectable_value(layout, MessageLayout::Compact, "Compact");
+                        ui.selectable_value(layout, MessageLayout::Cozy, "Cozy");
+                        ui.selectable_value(layout, MessageLayout::Irc, "IRC");
+                    });
+                if client.chat_settings.layout == MessageLayout::Cozy {
+                    ui.label("Avatar size:");
+                    ui.add(
+                        egui::Slider::new(&mut client.chat_settings.avatar_size, 0.0..=64.0)
+                            .suffix(" px"),
+                    );
+                    ui.label("Group messages within:");
+                    ui.add(
+                        egui::Slider::new(&mut client.chat_settings.group_window_secs, 0..=3600)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text("0 groups consecutive messages regardless of time");
+                }
 
-            ui.separator();
-            ui.label("Auto-embed assets:");
-            ui.checkbox(&mut client.chat_settings.auto_embed_emotes, "emotes");
-            ui.checkbox(&mut client.chat_settings.auto_embed_stickers, "stickers");
+                ui.separator();
+                ui.label("Timestamps:");
+                egui::ComboBox::from_id_salt("time_format")
+                    .selected_text(match client.chat_settings.time_format {
+                        TimeFormat::H24 => "24-hour",
+                        TimeFormat::H12 => "12-hour",
+                        TimeFormat::Relative => "Relative",
+                    })
+                    .show_ui(ui, |ui| {
+                        let format = &mut client.chat_settings.time_format;
+                        ui.selectable_value(format, TimeFormat::H24, "24-hour");
+                        ui.selectable_value(format, TimeFormat::H12, "12-hour");
+                        ui.selectable_value(format, TimeFormat::Relative, "Relative");
+                    });
+                ui.add_enabled(
+                    client.chat_settings.time_format != TimeFormat::Relative,
+                    egui::Checkbox::new(&mut client.chat_settings.show_seconds, "Show seconds"),
+                );
+                ui.checkbox(&mut client.chat_settings.day_separators, "Day separators");
+
+                ui.separator();
+                ui.label("Desktop notifications:");
+                ui.checkbox(
+                    &mut client.chat_settings.desktop_notifications,
+                    "Show when the window is unfocused",
+                );
+                if let Some(error) = client.desktop_error() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("Unavailable: {}", error),
+                    );
+                }
+                ui.add_enabled_ui(client.chat_settings.desktop_notifications, |ui| {
+                    ui.label("Content:");
+                    egui::ComboBox::from_id_salt("notification_privacy")
+                        .selected_text(match client.chat_settings.notification_privacy {
+                            NotificationPrivacy::Full => "Sender and message",
+                            NotificationPrivacy::HideText => "Sender only",
+                            NotificationPrivacy::HideAll => "Nothing",
+                        })
+                        .show_ui(ui, |ui| {
+                            let privacy = &mut client.chat_settings.notification_privacy;
+                            ui.selectable_value(
+                                privacy,
+                                NotificationPrivacy::Full,
+                                "Sender and message",
+                            );
+                            ui.selectable_value(
+                                privacy,
+                                NotificationPrivacy::HideText,
+                                "Sender only",
+                            );
+                            ui.selectable_value(privacy, NotificationPrivacy::HideAll, "Nothing");
+                        });
+                });
+
+                ui.separator();
+                ui.label("Auto-embed media:");
+                ui.checkbox(&mut client.chat_settings.auto_embed_images, "images");
+                ui.checkbox(&mut client.chat_settings.auto_embed_videos, "videos");
+                ui.checkbox(&mut client.chat_settings.auto_embed_audio, "audio");
+
+                ui.separator();
+                ui.label("Auto-embed assets:");
+                ui.checkbox(&mut client.chat_settings.auto_embed_emotes, "emotes");
+                ui.checkbox(&mut client.chat_settings.auto_embed_stickers, "stickers");
+
+                ui.separator();
+                ui.label("Media safety:");
+                ui.checkbox(
+                    &mut client.chat_settings.hide_embeds,
+                    "Hide embeds until clicked",
+                );
+                ui.label("Max download size:");
+                ui.add(
+                    egui::Slider::new(&mut client.chat_settings.max_embed_size_kb, 256..=65536)
+                        .logarithmic(true)
+                        .suffix(" KiB"),
+                );
+                ui.label("Max dimensions:");
+                ui.add(
+                    egui::Slider::new(&mut client.chat_settings.max_embed_dimension, 256..=16384)
+                        .logarithmic(true)
+                        .suffix(" px"),
+                );
+
+                ui.separator();
+                ui.label("Media cache:");
+                // Lowering the budget deletes files, so the slider only applies
+                // its value once it is released rather than at every step.
+                let budget_id = ui.make_persistent_id("cache_budget");
+                let mut budget_mb = ui
+                    .data(|d| d.get_temp::<u64>(budget_id))
+                    .unwrap_or(client.media_cache.budget() / MIB);
+                ui.label(format!(
+                    "{} / {}",
+                    format_size(client.media_cache.usage()),
+                    format_size(budget_mb * MIB)
+                ));
+                let response = ui.add(egui::Slider::new(&mut budget_mb, 16..=4096).suffix(" MiB"));
+                if response.changed() {
+                    ui.data_mut(|d| d.insert_temp(budget_id, budget_mb));
+                }
+                let settled = response.drag_stopped()
+                    || response.lost_focus()
+                    || !(response.dragged() || response.has_focus());
+                if settled && ui.data(|d| d.get_temp::<u64>(budget_id)).is_some() {
+                    ui.data_mut(|d| d.remove::<u64>(budget_id));
+                    client.media_cache.set_budget(budget_mb * MIB);
+                }
+                if ui.button("Clear cache").clicked() {
+                    client.media_cache.clear();
+                    ctx.forget_all_images();
+                }
+            });
+
+            if client.chat_settings != before {
+                client
+                    .media_cache
+                    .set_limits(client.chat_settings.media_limits());
+                client.save_settings_later();
+            }
         });
+    client
+        .session
+        .panel_widths
+        .insert("settings".to_string(), response.response.rect.width());
 }
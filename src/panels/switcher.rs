@@ -0,0 +1,159 @@
+use super::palette::{draw_picker, MAX_RESULTS};
+use crate::app::ChatClient;
+use crate::theme;
+use crate::utils::fuzzy_score;
+use chrono::{DateTime, Utc};
+use eframe::egui::{self, RichText};
+use oshatori::client::ConnectionStatus;
+
+enum Target {
+    Channel(String),
+    User { id: String, username: String },
+}
+
+struct Candidate {
+    connection_id: String,
+    target: Target,
+    label: String,
+    account: String,
+    unread: usize,
+    highlights: usize,
+    activity: Option<DateTime<Utc>>,
+}
+
+fn candidates(client: &ChatClient) -> Vec<Candidate> {
+    let cache = client.cache.lock().unwrap();
+    let mut candidates = Vec::new();
+
+    for conn in cache.connections.values() {
+        if conn.status != ConnectionStatus::Connected {
+            continue;
+        }
+        let account = cache
+            .accounts
+            .get(conn.account_index)
+            .and_then(|a| a.private_profile.as_ref())
+            .and_then(|p| p.username.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let prefs = cache
+            .account_prefs
+            .get(conn.account_index)
+            .cloned()
+            .unwrap_or_default();
+
+        for channel_id in &conn.channels {
+            let display = if channel_id.is_empty() {
+                "General"
+            } else {
+                channel_id
+            };
+            let muted = prefs.is_muted(channel_id);
+            candidates.push(Candidate {
+                connection_id: conn.connection_id.clone(),
+                target: Target::Channel(channel_id.clone()),
+                label: format!("#{}", display),
+                account: account.clone(),
+                unread: if muted {
+                    0
+                } else {
+                    conn.unread.get(channel_id).copied().unwrap_or(0)
+                },
+                highlights: if muted {
+                    0
+                } else {
+                    conn.highlights.get(channel_id).copied().unwrap_or(0)
+                },
+                activity: conn.last_activity.get(channel_id).copied(),
+            });
+        }
+
+        let own_id = conn.current_user.as_ref().and_then(|u| u.id.as_ref());
+        for (id, profile) in &conn.global_users {
+            if Some(id) == own_id || prefs.is_ignored(id, Some(profile)) {
+                continue;
+            }
+            let Some(username) = profile.username.clone() else {
+                continue;
+            };
+            candidates.push(Candidate {
+                connection_id: conn.connection_id.clone(),
+                target: Target::User {
+                    id: id.clone(),
+                    username,
+                },
+                label: format!("@{}", prefs.display_name(id, Some(profile))),
+                account: account.clone(),
+                unread: 0,
+                highlights: 0,
+                activity: None,
+            });
+        }
+    }
+    candidates
+}
+
+pub fn draw_switcher(client: &mut ChatClient, ctx: &egui::Context) {
+    let Some(mut state) = client.switcher.take() else {
+        return;
+    };
+
+    let blank = state.query.trim().is_empty();
+    let mut ranked: Vec<(i32, Candidate)> = candidates(client)
+        .into_iter()
+        .filter_map(|c| {
+            let score = if blank {
+                0
+            } else {
+                fuzzy_score(&state.query, &c.label)?
+            };
+            Some((score, c))
+        })
+        .collect();
+    ranked.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then((b.highlights > 0).cmp(&(a.highlights > 0)))
+            .then((b.unread > 0).cmp(&(a.unread > 0)))
+            .then(b.activity.cmp(&a.activity))
+            .then(a.label.cmp(&b.label))
+    });
+    ranked.truncate(MAX_RESULTS);
+
+    let colors = theme::colors(ctx);
+    let rows: Vec<(RichText, &str)> = ranked
+        .iter()
+        .map(|(_, c)| {
+            let mut label = RichText::new(if c.unread > 0 {
+                format!("{} ({})", c.label, c.unread)
+            } else {
+                c.label.clone()
+            });
+            if c.highlights > 0 {
+                label = label.color(colors.mention).strong();
+            } else if c.unread > 0 {
+                label = label.strong();
+            }
+            (label, c.account.as_str())
+        })
+        .collect();
+
+    let (open, chosen) = draw_picker(
+        ctx,
+        "Quick switcher",
+        "Jump to a channel or user...",
+        &mut state,
+        &rows,
+    );
+
+    match chosen.map(|i| &ranked[i].1) {
+        Some(candidate) => {
+            client.set_active_connection(candidate.connection_id.clone());
+            match &candidate.target {
+                Target::Channel(channel_id) => client.sync_selection(channel_id.clone()),
+                Target::User { id, username } => client.open_direct_message(ctx, id, username),
+            }
+        }
+        None if open => client.switcher = Some(state),
+        None => {}
+    }
+}
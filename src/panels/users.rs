@@ -1,15 +1,28 @@
 use crate::app::ChatClient;
-use crate::utils::color32;
-use eframe::egui::{self, Image, ScrollArea};
+use crate::state::{AccountPrefs, Presence, UserSort};
+use crate::theme;
+use crate::utils::{presence, user_color};
+use chrono::{DateTime, Utc};
+use eframe::egui::{self, Color32, Image, RichText, ScrollArea, TextEdit, Ui};
+use oshatori::Profile;
+use std::collections::HashMap;
+
+struct UserRow {
+    id: String,
+    profile: Profile,
+    name: String,
+    presence: Option<Presence>,
+    last_active: Option<DateTime<Utc>>,
+}
 
 pub fn draw_users(client: &mut ChatClient, ctx: &egui::Context) {
     if !client.panels.users {
         return;
     }
 
-    egui::SidePanel::right("users")
+    let response = egui::SidePanel::right("users")
         .resizable(true)
-        .default_width(360.0)
+        .default_width(client.session.panel_width("users", 360.0))
         .show(ctx, |ui| {
             ui.heading("Users");
             ui.separator();
@@ -17,52 +30,176 @@ pub fn draw_users(client: &mut ChatClient, ctx: &egui::Context) {
             let conn = client.active_connection();
 
             if let Some(conn) = conn {
+                let prefs = client.account_prefs(conn.account_index);
+                let capabilities = client.capabilities(conn.account_index);
+
+                ui.horizontal(|ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut client.user_filter)
+                            .desired_width(ui.available_width() - 90.0)
+                            .hint_text("Filter users..."),
+                    );
+                    let before = client.chat_settings.user_sort;
+                    egui::ComboBox::from_id_salt("user_sort")
+                        .width(80.0)
+                        .selected_text(match client.chat_settings.user_sort {
+                            UserSort::Name => "Name",
+                            UserSort::Activity => "Activity",
+                        })
+                        .show_ui(ui, |ui| {
+                            let sort = &mut client.chat_settings.user_sort;
+                            ui.selectable_value(sort, UserSort::Name, "Name");
+                            ui.selectable_value(sort, UserSort::Activity, "Activity");
+                        });
+                    if client.chat_settings.user_sort != before {
+                        client.save_settings_later();
+                    }
+                });
+                ui.separator();
+
+                let mut last_active: HashMap<&str, DateTime<Utc>> = HashMap::new();
+                let channel_users = conn.current_channel.as_ref().map(|ch| &ch.users);
+                if let Some(ch) = &conn.current_channel {
+                    for msg in &ch.messages {
+                        if let Some(id) = &msg.sender_id {
+                            last_active.insert(id, msg.timestamp);
+                        }
+                    }
+                }
+
+                let filter = client.user_filter.trim().to_lowercase();
+                let mut here = Vec::new();
+                let mut elsewhere = Vec::new();
                 let mut all_users = conn.global_users.clone();
-                if let Some(ref channel_state) = conn.current_channel {
-                    for (id, user) in &channel_state.users {
-                        all_users.insert(id.clone(), user.clone());
+                if let Some(users) = channel_users {
+                    all_users.extend(users.clone());
+                }
+
+                for (id, profile) in all_users {
+                    let name = prefs.display_name(&id, Some(&profile));
+                    let matches = filter.is_empty()
+                        || name.to_lowercase().contains(&filter)
+                        || profile
+                            .username
+                            .as_ref()
+                            .is_some_and(|u| u.to_lowercase().contains(&filter));
+                    if !matches {
+                        continue;
+                    }
+
+                    let in_channel = channel_users.is_some_and(|users| users.contains_key(&id));
+                    let row = UserRow {
+                        presence: presence(&profile, capabilities),
+                        last_active: last_active.get(id.as_str()).copied(),
+                        id,
+                        profile,
+                        name,
+                    };
+                    if in_channel {
+                        here.push(row);
+                    } else {
+                        elsewhere.push(row);
                     }
                 }
 
-                if !all_users.is_empty() {
-                    let mut sorted: Vec<_> = all_users.into_iter().collect();
-                    sorted.sort_by(|a, b| {
-                        let name_a = a.1.username.as_deref().unwrap_or("");
-                        let name_b = b.1.username.as_deref().unwrap_or("");
-                        name_a.to_lowercase().cmp(&name_b.to_lowercase())
-                    });
-
-                    ScrollArea::vertical().show(ui, |ui| {
-                        for (_, user) in &sorted {
-                            let name = user
-                                .display_name
-                                .as_ref()
-                                .or(user.username.as_ref())
-                                .map(|s| s.as_str())
-                                .unwrap_or("Unknown");
-
-                            ui.horizontal(|ui| {
-                                if let Some(pic) = &user.picture {
-                                    ui.add(
-                                        Image::from_uri(pic)
-                                            .fit_to_exact_size(egui::Vec2::new(24.0, 24.0))
-                                            .rounding(egui::Rounding::same(2.0)),
-                                    );
-                                }
-
-                                if let Some(color) = user.color {
-                                    ui.colored_label(color32(color), name);
-                                } else {
-                                    ui.label(name);
-                                }
-                            });
-                        }
-                    });
-                } else {
+                if here.is_empty() && elsewhere.is_empty() {
                     ui.label("No users to display");
+                    return;
                 }
+
+                for rows in [&mut here, &mut elsewhere] {
+                    match client.chat_settings.user_sort {
+                        UserSort::Name => {
+                            rows.sort_by_cached_key(|r| (r.presence, r.name.to_lowercase()))
+                        }
+                        UserSort::Activity => rows.sort_by_cached_key(|r| {
+                            (
+                                r.presence,
+                                std::cmp::Reverse(r.last_active),
+                                r.name.to_lowercase(),
+                            )
+                        }),
+                    }
+                }
+
+                ScrollArea::vertical().show(ui, |ui| {
+                    if !here.is_empty() {
+                        ui.label(
+                            RichText::new(format!("In this channel — {}", here.len()))
+                                .small()
+                                .color(Color32::GRAY),
+                        );
+                        draw_user_rows(client, ui, &here, &prefs);
+                    }
+                    if !elsewhere.is_empty() {
+                        ui.add_space(6.0);
+                        ui.label(
+                            RichText::new(format!(
+                                "Elsewhere on this server — {}",
+                                elsewhere.len()
+                            ))
+                            .small()
+                            .color(Color32::GRAY),
+                        );
+                        draw_user_rows(client, ui, &elsewhere, &prefs);
+                    }
+                });
             } else {
                 ui.label("No active connection");
             }
         });
+    client
+        .session
+        .panel_widths
+        .insert("users".to_string(), response.response.rect.width());
+}
+
+fn draw_user_rows(client: &mut ChatClient, ui: &mut Ui, rows: &[UserRow], prefs: &AccountPrefs) {
+    let mut last_presence = None;
+    for row in rows {
+        if last_presence.is_some() && last_presence != row.presence {
+            ui.add_space(4.0);
+        }
+        last_presence = row.presence;
+
+        ui.horizontal(|ui| {
+            if let Some(presence) = row.presence {
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::Vec2::splat(8.0), egui::Sense::hover());
+                let dot = match presence {
+                    Presence::Online => theme::colors(ui.ctx()).online,
+                    Presence::Away => theme::colors(ui.ctx()).away,
+                };
+                ui.painter().circle_filled(rect.center(), 4.0, dot);
+            }
+
+            if let Some(pic) = row.profile.picture.as_ref().filter(|p| prefs.auto_embed(p)) {
+                ui.add(
+                    Image::from_uri(pic)
+                        .fit_to_exact_size(egui::Vec2::new(24.0, 24.0))
+                        .rounding(egui::Rounding::same(2.0)),
+                );
+            }
+
+            let mut text = RichText::new(&row.name);
+            if let Some(color) = user_color(
+                &row.id,
+                Some(&row.profile),
+                prefs,
+                &client.chat_settings,
+                ui.visuals().panel_fill,
+            ) {
+                text = text.color(color);
+            }
+            if prefs.is_ignored(&row.id, Some(&row.profile)) {
+                text = text.strikethrough();
+            }
+            if ui
+                .add(egui::Label::new(text).sense(egui::Sense::click()))
+                .clicked()
+            {
+                client.profile_user = Some(row.id.clone());
+            }
+        });
+    }
 }
@@ -0,0 +1,396 @@
+use eframe::egui::{self, load::BytesPoll};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Default)]
+pub struct LinkPreview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub image: Option<String>,
+}
+
+impl LinkPreview {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image.is_none()
+    }
+}
+
+pub enum PreviewPoll {
+    Pending,
+    Ready(Arc<LinkPreview>),
+    Unavailable,
+}
+
+#[derive(Clone)]
+enum CachedPreview {
+    Ready(Arc<LinkPreview>),
+    // The page loaded but isn't HTML or has nothing to show.
+    Unavailable,
+    // The request failed, which may be temporary.
+    Failed(Instant),
+}
+
+pub fn load_preview(ctx: &egui::Context, url: &str) -> PreviewPoll {
+    let id = egui::Id::new(("link_preview", url));
+    match ctx.data(|d| d.get_temp::<CachedPreview>(id)) {
+        Some(CachedPreview::Ready(preview)) => return PreviewPoll::Ready(preview),
+        Some(CachedPreview::Unavailable) => return PreviewPoll::Unavailable,
+        Some(CachedPreview::Failed(at)) if at.elapsed() < RETRY_AFTER => {
+            return PreviewPoll::Unavailable
+        }
+        Some(CachedPreview::Failed(_)) => {
+            ctx.data_mut(|d| d.remove::<CachedPreview>(id));
+            ctx.forget_image(url);
+        }
+        None => {}
+    }
+
+    let cached = match ctx.try_load_bytes(url) {
+        Ok(BytesPoll::Pending { .. }) => return PreviewPoll::Pending,
+        Ok(BytesPoll::Ready { bytes, mime, .. }) => {
+            let is_html = mime.as_deref().map(|m| m.contains("html")).unwrap_or(true);
+            let preview = if is_html {
+                parse_preview(&String::from_utf8_lossy(&bytes), url)
+            } else {
+                None
+            };
+            match preview {
+                Some(preview) => CachedPreview::Ready(Arc::new(preview)),
+                None => CachedPreview::Unavailable,
+            }
+        }
+        Err(_) => CachedPreview::Failed(Instant::now()),
+    };
+
+    ctx.data_mut(|d| d.insert_temp(id, cached.clone()));
+    match cached {
+        CachedPreview::Ready(preview) => PreviewPoll::Ready(preview),
+        _ => PreviewPoll::Unavailable,
+    }
+}
+
+pub fn parse_preview(html: &str, base: &str) -> Option<LinkPreview> {
+    let lower = html.to_ascii_lowercase();
+    let head_end = lower.find("</head>").unwrap_or(lower.len());
+    let mut preview = LinkPreview::default();
+    let mut fallback_title = None;
+    let mut fallback_description = None;
+
+    if let Some(start) = lower[..head_end].find("<title") {
+        if let Some(open_end) = lower[start..head_end].find('>') {
+            let text_start = start + open_end + 1;
+            if let Some(close) = lower[text_start..head_end].find("</title") {
+                fallback_title = non_empty(decode_entities(&html[text_start..text_start + close]));
+            }
+        }
+    }
+
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..head_end].find("<meta") {
+        let start = pos + offset;
+        let Some(end) = lower[start..head_end].find('>').map(|e| start + e) else {
+            break;
+        };
+        let attrs = parse_attributes(&html[start + 5..end]);
+        let key = attrs
+            .iter()
+            .find(|(k, _)| k == "property" || k == "name")
+            .map(|(_, v)| v.to_lowercase());
+        let content = attrs
+            .iter()
+            .find(|(k, _)| k == "content")
+            .and_then(|(_, v)| non_empty(decode_entities(v)));
+
+        if let (Some(key), Some(content)) = (key, content) {
+            match key.as_str() {
+                "og:title" => preview.title = Some(content),
+                "og:description" => preview.description = Some(content),
+                "og:site_name" => preview.site_name = Some(content),
+                "og:image" | "og:image:url" => {
+                    preview.image = preview.image.or_else(|| resolve(base, &content))
+                }
+                "twitter:title" => fallback_title = fallback_title.or(Some(content)),
+                "twitter:image" => {
+                    preview.image = preview.image.or_else(|| resolve(base, &content))
+                }
+                "description" | "twitter:description" => {
+                    fallback_description = fallback_description.or(Some(content))
+                }
+                _ => {}
+            }
+        }
+        pos = end;
+    }
+
+    preview.title = preview.title.or(fallback_title);
+    preview.description = preview.description.or(fallback_description);
+    if preview.site_name.is_none() {
+        preview.site_name = url::Url::parse(base)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()));
+    }
+
+    if preview.is_empty() {
+        None
+    } else {
+        Some(preview)
+    }
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut chars = tag.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == '/') {
+            chars.next();
+        }
+        let name: String =
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != '/'))
+                .collect();
+        if name.is_empty() {
+            break;
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => value = chars.by_ref().take_while(|c| *c != quote).collect(),
+                None => {
+                    value = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect()
+                }
+            }
+        }
+        attrs.push((name.to_ascii_lowercase(), value));
+    }
+
+    attrs
+}
+
+fn decode_entities(text: &str) -> String {
+    text.trim()
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+fn resolve(base: &str, url: &str) -> Option<String> {
+    url::Url::parse(base)
+        .and_then(|b| b.join(url))
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .map(|u| u.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MediaCache;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::runtime::Runtime;
+
+    const BASE: &str = "https://example.com/articles/1";
+
+    fn parse(head: &str) -> Option<LinkPreview> {
+        parse_preview(
+            &format!("<html><head>{head}</head><body></body></html>"),
+            BASE,
+        )
+    }
+
+    #[test]
+    fn reads_open_graph_tags() {
+        let preview = parse(
+            r#"<meta property="og:title" content="Title">
+               <meta property="og:description" content="Description">
+               <meta property="og:site_name" content="Example">
+               <meta property="og:image" content="https://cdn.example.com/a.png">"#,
+        )
+        .unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Title"));
+        assert_eq!(preview.description.as_deref(), Some("Description"));
+        assert_eq!(preview.site_name.as_deref(), Some("Example"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://cdn.example.com/a.png")
+        );
+    }
+
+    #[test]
+    fn accepts_any_attribute_order_and_quoting() {
+        let preview = parse(
+            r#"<META content='Single quoted' PROPERTY='og:title' />
+               <meta content=Unquoted name=og:description>"#,
+        )
+        .unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Single quoted"));
+        assert_eq!(preview.description.as_deref(), Some("Unquoted"));
+    }
+
+    #[test]
+    fn mixed_quotes_keep_the_other_quote_in_values() {
+        let preview = parse(r#"<meta property="og:title" content="It's here">"#).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("It's here"));
+    }
+
+    #[test]
+    fn accepts_name_as_well_as_property() {
+        let preview = parse(
+            r#"<meta name="og:title" content="By name">
+               <meta name="description" content="Plain description">"#,
+        )
+        .unwrap();
+        assert_eq!(preview.title.as_deref(), Some("By name"));
+        assert_eq!(preview.description.as_deref(), Some("Plain description"));
+    }
+
+    #[test]
+    fn open_graph_wins_over_fallbacks() {
+        let preview = parse(
+            r#"<title>Page title</title>
+               <meta name="description" content="Fallback">
+               <meta name="twitter:title" content="Twitter title">
+               <meta property="og:description" content="Preferred">"#,
+        )
+        .unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Page title"));
+        assert_eq!(preview.description.as_deref(), Some("Preferred"));
+
+        let preview = parse(r#"<meta name="twitter:title" content="Twitter title">"#).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Twitter title"));
+    }
+
+    #[test]
+    fn decodes_entities() {
+        let preview = parse(
+            r#"<title> Fish &amp; Chips </title>
+               <meta property="og:description" content="&quot;quoted&quot; &#39;a&#x27; &lt;b&gt;&nbsp;&amp;amp;">"#,
+        )
+        .unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Fish & Chips"));
+        assert_eq!(
+            preview.description.as_deref(),
+            Some("\"quoted\" 'a' <b> &amp;")
+        );
+    }
+
+    #[test]
+    fn resolves_relative_images_and_rejects_other_schemes() {
+        let preview = parse(r#"<meta property="og:image" content="/img/cover.jpg">"#).unwrap();
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/img/cover.jpg")
+        );
+
+        assert!(parse(r#"<meta property="og:image" content="javascript:alert(1)">"#).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_host_for_site_name() {
+        let preview = parse("<title>Only a title</title>").unwrap();
+        assert_eq!(preview.site_name.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn missing_tags_give_no_preview() {
+        assert!(parse("").is_none());
+        assert!(parse(r#"<meta property="og:title" content="">"#).is_none());
+        assert!(parse(r#"<meta property="og:title">"#).is_none());
+        assert!(parse_preview("not html at all", BASE).is_none());
+        assert!(parse_preview(
+            r#"<head></head><body><meta property="og:title" content="Body"></body>"#,
+            BASE
+        )
+        .is_none());
+    }
+
+    fn respond(path: &str) -> String {
+        let (status, mime, body) = match path {
+            "/page" => (
+                "200 OK",
+                "text/html; charset=utf-8",
+                r#"<html><head><meta property="og:title" content="Served"></head></html>"#,
+            ),
+            "/image" => ("200 OK", "image/png", "not really a png"),
+            _ => ("404 Not Found", "text/plain", "missing"),
+        };
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {mime}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn fetches_previews_from_a_server() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        runtime.spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    socket.write_all(respond(path).as_bytes()).await.ok();
+                });
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("taitsu-preview-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ctx = egui::Context::default();
+        ctx.add_bytes_loader(Arc::new(MediaCache::with_dir(runtime.clone(), dir.clone())));
+
+        let load = |path: &str| {
+            let url = format!("http://{addr}{path}");
+            for _ in 0..500 {
+                match load_preview(&ctx, &url) {
+                    PreviewPoll::Pending => std::thread::sleep(Duration::from_millis(10)),
+                    poll => return poll,
+                }
+            }
+            panic!("preview for {url} never finished loading");
+        };
+
+        match load("/page") {
+            PreviewPoll::Ready(preview) => assert_eq!(preview.title.as_deref(), Some("Served")),
+            _ => panic!("expected a preview for /page"),
+        }
+        assert!(matches!(load("/image"), PreviewPoll::Unavailable));
+        assert!(matches!(load("/missing"), PreviewPoll::Unavailable));
+        // Failures are remembered until the retry window passes.
+        let missing = format!("http://{addr}/missing");
+        assert!(matches!(
+            ctx.data(
+                |d| d.get_temp::<CachedPreview>(egui::Id::new(("link_preview", missing.as_str())))
+            ),
+            Some(CachedPreview::Failed(_))
+        ));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -1,6 +1,9 @@
+use crate::media::MediaLimits;
+use chrono::{DateTime, Utc};
 use oshatori::client::ConnectionStatus;
-use oshatori::{client::ChannelState, Account, Asset, Message, Profile};
-use std::collections::HashMap;
+use oshatori::{client::ChannelState, Account, Asset, AuthField, FieldValue, Message, Profile};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
 #[derive(Clone, Default)]
@@ -9,23 +12,296 @@ pub struct ConnectionCache {
     pub account_index: usize,
     pub status: ConnectionStatus,
     pub channels: Vec<String>,
+    pub channel_users: HashMap<String, Vec<String>>,
     pub current_channel: Option<ChannelState>,
     pub assets: HashMap<String, Asset>,
     pub global_users: HashMap<String, Profile>,
     pub current_user: Option<Profile>,
-    pub pending_messages: Vec<Message>,
-    pub last_ping: Option<Instant>,
+    pub pending_messages: HashMap<String, Vec<Message>>,
+    pub tombstones: HashMap<String, Vec<Message>>,
+    pub read_until: HashMap<String, DateTime<Utc>>,
+    pub unread: HashMap<String, usize>,
+    pub highlights: HashMap<String, usize>,
+    pub last_activity: HashMap<String, DateTime<Utc>>,
+    pub channel_states: HashMap<String, ChannelState>,
+    pub notified_until: HashMap<String, DateTime<Utc>>,
+    pub new_notifications: Vec<Notification>,
 }
 
 #[derive(Clone, Default)]
 pub struct UiCache {
     pub accounts: Vec<Account>,
+    pub account_prefs: Vec<AccountPrefs>,
     pub connections: HashMap<String, ConnectionCache>,
     pub active_connection: Option<String>,
+    pub watched_channels: HashSet<(usize, String)>,
+    pub notifications: Vec<Notification>,
+    pub next_notification: u64,
     pub updated: Option<Instant>,
 }
 
-#[derive(Default)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountPrefs {
+    pub trusted_domains: Vec<String>,
+    pub blocked_domains: Vec<String>,
+    pub trusted_only: bool,
+    pub link_previews: bool,
+    pub preview_untrusted: bool,
+    pub no_preview_domains: Vec<String>,
+    pub ignored_users: Vec<String>,
+    pub ignored_patterns: Vec<String>,
+    pub muted_channels: Vec<String>,
+    pub nicknames: HashMap<String, String>,
+    pub colors: HashMap<String, [u8; 4]>,
+    pub favorite_channels: Vec<String>,
+    pub channel_order: Vec<String>,
+    pub channel_folders: HashMap<String, String>,
+    pub notify_level: NotifyLevel,
+    pub channel_notify: HashMap<String, NotifyLevel>,
+}
+
+impl Default for AccountPrefs {
+    fn default() -> Self {
+        AccountPrefs {
+            trusted_domains: Vec::new(),
+            blocked_domains: Vec::new(),
+            trusted_only: false,
+            link_previews: true,
+            preview_untrusted: false,
+            no_preview_domains: Vec::new(),
+            ignored_users: Vec::new(),
+            ignored_patterns: Vec::new(),
+            muted_channels: Vec::new(),
+            nicknames: HashMap::new(),
+            colors: HashMap::new(),
+            favorite_channels: Vec::new(),
+            channel_order: Vec::new(),
+            channel_folders: HashMap::new(),
+            notify_level: NotifyLevel::default(),
+            channel_notify: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum NotifyLevel {
+    All,
+    #[default]
+    Mentions,
+    None,
+}
+
+impl NotifyLevel {
+    pub const ALL: [NotifyLevel; 3] = [NotifyLevel::All, NotifyLevel::Mentions, NotifyLevel::None];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotifyLevel::All => "All messages",
+            NotifyLevel::Mentions => "Mentions and direct messages",
+            NotifyLevel::None => "Nothing",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NotificationPrivacy {
+    Full,
+    HideText,
+    HideAll,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Message,
+    Mention,
+    Direct,
+}
+
+#[derive(Clone)]
+pub struct Notification {
+    pub id: u64,
+    pub account_index: usize,
+    pub connection_id: String,
+    pub channel_id: String,
+    pub sender: String,
+    pub sender_picture: Option<String>,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub kind: NotificationKind,
+    pub read: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Trust {
+    Trusted,
+    Untrusted,
+    Blocked,
+}
+
+fn url_host(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            p = bp + 1;
+            t = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn host_matches(host: &str, domains: &[String]) -> bool {
+    domains.iter().any(|domain| {
+        let domain = domain.trim().to_lowercase();
+        !domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain)))
+    })
+}
+
+impl AccountPrefs {
+    pub fn trust(&self, url: &str) -> Trust {
+        let Some(host) = url_host(url) else {
+            return Trust::Blocked;
+        };
+
+        if host_matches(&host, &self.blocked_domains) {
+            Trust::Blocked
+        } else if host_matches(&host, &self.trusted_domains) {
+            Trust::Trusted
+        } else {
+            Trust::Untrusted
+        }
+    }
+
+    pub fn auto_embed(&self, url: &str) -> bool {
+        match self.trust(url) {
+            Trust::Trusted => true,
+            Trust::Untrusted => !self.trusted_only,
+            Trust::Blocked => false,
+        }
+    }
+
+    pub fn display_name(&self, user_id: &str, profile: Option<&Profile>) -> String {
+        self.nicknames
+            .get(user_id)
+            .cloned()
+            .or_else(|| profile.and_then(|p| p.display_name.clone().or(p.username.clone())))
+            .unwrap_or_else(|| user_id.to_string())
+    }
+
+    pub fn is_ignored(&self, user_id: &str, profile: Option<&Profile>) -> bool {
+        if self.ignored_users.iter().any(|id| id == user_id) {
+            return true;
+        }
+
+        let names = profile
+            .into_iter()
+            .flat_map(|p| [p.username.as_deref(), p.display_name.as_deref()])
+            .flatten();
+        names.into_iter().any(|name| {
+            self.ignored_patterns
+                .iter()
+                .any(|pattern| !pattern.trim().is_empty() && wildcard_match(pattern.trim(), name))
+        })
+    }
+
+    pub fn is_favorite(&self, channel_id: &str) -> bool {
+        self.favorite_channels.iter().any(|id| id == channel_id)
+    }
+
+    pub fn sort_channels(&self, channels: &mut [String]) {
+        channels.sort_by_cached_key(|id| {
+            (
+                self.channel_order
+                    .iter()
+                    .position(|c| c == id)
+                    .unwrap_or(usize::MAX),
+                id.to_lowercase(),
+            )
+        });
+    }
+
+    // `channels` are the ones currently listed. Saved positions of channels
+    // that aren't listed right now are kept, so they come back where they were
+    // after a reconnect. Dropping on a channel that isn't listed does nothing.
+    pub fn move_channel(&mut self, channel_id: &str, before: &str, channels: &[String]) {
+        if channel_id == before || !channels.iter().any(|id| id == before) {
+            return;
+        }
+
+        let mut unsaved: Vec<String> = channels
+            .iter()
+            .filter(|id| !self.channel_order.contains(id))
+            .cloned()
+            .collect();
+        self.sort_channels(&mut unsaved);
+        let mut order = self.channel_order.clone();
+        order.extend(unsaved);
+        order.retain(|id| id != channel_id);
+        let index = order
+            .iter()
+            .position(|id| id == before)
+            .unwrap_or(order.len());
+        order.insert(index, channel_id.to_string());
+        self.channel_order = order;
+
+        match self.channel_folders.get(before).cloned() {
+            Some(folder) => self.channel_folders.insert(channel_id.to_string(), folder),
+            None => self.channel_folders.remove(channel_id),
+        };
+        let favorite = self.is_favorite(before);
+        self.favorite_channels.retain(|id| id != channel_id);
+        if favorite {
+            self.favorite_channels.push(channel_id.to_string());
+        }
+    }
+
+    pub fn is_muted(&self, channel_id: &str) -> bool {
+        self.muted_channels.iter().any(|id| id == channel_id)
+    }
+
+    pub fn notify_level(&self, channel_id: &str) -> NotifyLevel {
+        self.channel_notify
+            .get(channel_id)
+            .copied()
+            .unwrap_or(self.notify_level)
+    }
+
+    // Fetching a preview reveals the user's IP to the linked host, so only
+    // trusted hosts are previewed unless the user opts in.
+    pub fn preview(&self, url: &str) -> bool {
+        let allowed = match self.trust(url) {
+            Trust::Trusted => true,
+            Trust::Untrusted => self.preview_untrusted && !self.trusted_only,
+            Trust::Blocked => false,
+        };
+        self.link_previews
+            && allowed
+            && url_host(url).is_some_and(|host| !host_matches(&host, &self.no_preview_domains))
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Panels {
     pub accounts: bool,
     pub channels: bool,
@@ -35,7 +311,219 @@ pub struct Panels {
     pub settings: bool,
 }
 
-#[derive(Clone)]
+impl Default for Panels {
+    fn default() -> Self {
+        Panels {
+            accounts: true,
+            channels: true,
+            users: true,
+            chat: true,
+            input: true,
+            settings: false,
+        }
+    }
+}
+
+// Accounts are stored as a list, so their indexes shift when one is deleted.
+// Anything saved across restarts refers to an account by its protocol and
+// login fields instead. Passwords are left out so they never reach the
+// session file.
+pub fn account_key(account: &Account) -> String {
+    fn push_fields(key: &mut String, fields: &[AuthField]) {
+        for field in fields {
+            match &field.value {
+                FieldValue::Text(value) => {
+                    key.push('\n');
+                    key.push_str(&field.name);
+                    key.push('=');
+                    key.push_str(value.as_deref().unwrap_or(""));
+                }
+                FieldValue::Password(_) => {}
+                FieldValue::Group(fields) => push_fields(key, fields),
+            }
+        }
+    }
+
+    let mut key = account.protocol_name.clone();
+    push_fields(&mut key, &account.auth);
+    key
+}
+
+pub fn find_account(accounts: &[Account], key: &str) -> Option<usize> {
+    accounts.iter().position(|a| account_key(a) == key)
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct PopoutWindow {
+    pub account: String,
+    pub channel_id: String,
+    pub position: Option<[f32; 2]>,
+    pub size: [f32; 2],
+}
+
+impl PopoutWindow {
+    pub fn new(account: String, channel_id: String) -> Self {
+        PopoutWindow {
+            account,
+            channel_id,
+            position: None,
+            size: [480.0, 600.0],
+        }
+    }
+}
+
+// Composer and scroll state of a split pane or pop-out, which the main view
+// keeps directly on the client.
+#[derive(Default)]
+pub struct PaneState {
+    pub composer: String,
+    pub replying_to: Option<Message>,
+    pub editing_message: Option<Message>,
+    pub scroll_to_message: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelPane {
+    pub account: String,
+    pub channel_id: String,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SplitDirection {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    pub popouts: Vec<PopoutWindow>,
+    pub panes: Vec<ChannelPane>,
+    pub split: SplitDirection,
+    pub window_position: Option<[f32; 2]>,
+    pub window_size: Option<[f32; 2]>,
+    pub maximized: bool,
+    pub panels: Panels,
+    pub panel_widths: HashMap<String, f32>,
+    pub active_account: Option<String>,
+    pub active_channel: Option<String>,
+}
+
+impl SessionState {
+    pub fn panel_width(&self, panel: &str, default: f32) -> f32 {
+        self.panel_widths.get(panel).copied().unwrap_or(default)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Capabilities {
+    pub edit_messages: bool,
+    pub text_commands: bool,
+    pub leave_channels: bool,
+    pub away_name_prefix: bool,
+}
+
+impl Capabilities {
+    // What each backend can actually put on the wire. oshatori's sockchat
+    // connection only forwards the text of new messages and drops every other
+    // event, so edits and deletes would never reach the server there.
+    pub fn for_protocol(protocol: &str) -> Self {
+        match protocol {
+            "Mock" => Capabilities {
+                edit_messages: true,
+                text_commands: false,
+                leave_channels: true,
+                away_name_prefix: false,
+            },
+            // Channels and whispers are driven by sockchat's own slash commands.
+            // A user is always in exactly one channel, so there is nothing to
+            // leave, and /afk is only visible as a prefix on the user's name.
+            "sockchat" => Capabilities {
+                edit_messages: false,
+                text_commands: true,
+                leave_channels: false,
+                away_name_prefix: true,
+            },
+            _ => Capabilities::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelCommand {
+    Join {
+        name: String,
+        password: Option<String>,
+    },
+    Create {
+        name: String,
+        password: Option<String>,
+    },
+    Leave(Option<String>),
+    Browse,
+}
+
+impl ChannelCommand {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let command = parts.next()?.strip_prefix('/')?.to_lowercase();
+        let name = parts.next().map(|s| s.to_string());
+        let password = parts.next().map(|s| s.to_string());
+
+        match command.as_str() {
+            "join" => Some(ChannelCommand::Join {
+                name: name?,
+                password,
+            }),
+            "create" => Some(ChannelCommand::Create {
+                name: name?,
+                password,
+            }),
+            "leave" | "part" => Some(ChannelCommand::Leave(name)),
+            "list" | "channels" => Some(ChannelCommand::Browse),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontSettings {
+    pub ui: Option<String>,
+    pub chat: Option<String>,
+    pub monospace: Option<String>,
+    pub fallbacks: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MessageLayout {
+    Compact,
+    Cozy,
+    Irc,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UserSort {
+    Name,
+    Activity,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Presence {
+    Online,
+    Away,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TimeFormat {
+    H24,
+    H12,
+    Relative,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ChatSettings {
     pub autoscroll: bool,
     pub auto_embed_images: bool,
@@ -43,8 +531,32 @@ pub struct ChatSettings {
     pub auto_embed_audio: bool,
     pub auto_embed_emotes: bool,
     pub auto_embed_stickers: bool,
+    pub max_embed_size_kb: u64,
+    pub max_embed_dimension: u32,
+    #[serde(alias = "blur_embeds")]
+    pub hide_embeds: bool,
+    pub show_deleted_messages: bool,
+    pub time_format: TimeFormat,
+    pub user_sort: UserSort,
+    pub show_seconds: bool,
+    pub day_separators: bool,
+    pub collapse_ignored: bool,
+    pub auto_user_colors: bool,
+    pub readable_colors: bool,
+    pub theme: String,
+    pub fonts: FontSettings,
+    pub layout: MessageLayout,
+    pub avatar_size: f32,
+    pub group_window_secs: u64,
+    pub ui_scale: f32,
+    pub chat_font_size: f32,
+    pub desktop_notifications: bool,
+    pub notification_privacy: NotificationPrivacy,
+    #[serde(skip)]
     pub last_message_count: usize,
+    #[serde(skip)]
     pub embed_generation: usize,
+    #[serde(skip)]
     pub unembed_override: bool,
 }
 
@@ -57,9 +569,264 @@ impl Default for ChatSettings {
             auto_embed_audio: false,
             auto_embed_emotes: true,
             auto_embed_stickers: true,
+            max_embed_size_kb: 8 * 1024,
+            max_embed_dimension: 4096,
+            hide_embeds: false,
+            show_deleted_messages: true,
+            time_format: TimeFormat::H24,
+            user_sort: UserSort::Name,
+            show_seconds: true,
+            day_separators: true,
+            collapse_ignored: true,
+            auto_user_colors: false,
+            readable_colors: true,
+            theme: "Dark".to_string(),
+            fonts: FontSettings::default(),
+            layout: MessageLayout::Cozy,
+            avatar_size: 32.0,
+            group_window_secs: 0,
+            ui_scale: 1.0,
+            chat_font_size: 14.0,
+            desktop_notifications: true,
+            notification_privacy: NotificationPrivacy::Full,
             last_message_count: 0,
             embed_generation: 0,
             unembed_override: false,
         }
     }
 }
+
+impl ChatSettings {
+    pub fn media_limits(&self) -> MediaLimits {
+        MediaLimits {
+            max_bytes: self.max_embed_size_kb * 1024,
+            max_dimension: self.max_embed_dimension,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(username: &str, display_name: Option<&str>) -> Profile {
+        Profile {
+            id: Some("1".to_string()),
+            username: Some(username.to_string()),
+            display_name: display_name.map(|n| n.to_string()),
+            color: None,
+            picture: None,
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn ignoring(patterns: &[&str]) -> AccountPrefs {
+        AccountPrefs {
+            ignored_patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wildcard_match_supports_star_and_question_mark() {
+        assert!(wildcard_match("spam*bot", "spambot"));
+        assert!(wildcard_match("spam*bot", "spam-the-bot"));
+        assert!(!wildcard_match("spam*bot", "spambots"));
+        assert!(wildcard_match("*bot*", "a robot army"));
+        assert!(wildcard_match("user?", "user7"));
+        assert!(!wildcard_match("user?", "user"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn wildcard_match_is_case_insensitive_and_anchored() {
+        assert!(wildcard_match("SpamBot", "spambot"));
+        assert!(!wildcard_match("spam", "spammer"));
+        assert!(!wildcard_match("spam", "not spam"));
+    }
+
+    #[test]
+    fn ignores_by_id_or_name_pattern() {
+        let mut prefs = ignoring(&["spam*", "  ", "*[bot]"]);
+        prefs.ignored_users.push("42".to_string());
+
+        assert!(prefs.is_ignored("42", None));
+        assert!(prefs.is_ignored("7", Some(&profile("spammer", None))));
+        assert!(prefs.is_ignored("7", Some(&profile("alice", Some("Helper [bot]")))));
+        assert!(!prefs.is_ignored("7", Some(&profile("alice", Some("Alice")))));
+        // Blank patterns never match everything.
+        assert!(!prefs.is_ignored("7", Some(&profile("", None))));
+        assert!(!prefs.is_ignored("7", None));
+    }
+
+    #[test]
+    fn move_channel_places_channel_before_target() {
+        let channels = names(&["alpha", "beta", "gamma", "delta"]);
+        let mut prefs = AccountPrefs::default();
+
+        prefs.move_channel("delta", "beta", &channels);
+        assert_eq!(
+            prefs.channel_order,
+            names(&["alpha", "delta", "beta", "gamma"])
+        );
+
+        prefs.move_channel("alpha", "gamma", &channels);
+        assert_eq!(
+            prefs.channel_order,
+            names(&["delta", "beta", "alpha", "gamma"])
+        );
+    }
+
+    #[test]
+    fn move_channel_keeps_positions_of_unlisted_channels() {
+        let mut prefs = AccountPrefs {
+            channel_order: names(&["alpha", "away", "beta", "gamma"]),
+            ..Default::default()
+        };
+        prefs.move_channel("gamma", "alpha", &names(&["alpha", "beta", "gamma", "new"]));
+        assert_eq!(
+            prefs.channel_order,
+            names(&["gamma", "alpha", "away", "beta", "new"])
+        );
+    }
+
+    #[test]
+    fn move_channel_ignores_unknown_targets() {
+        let channels = names(&["alpha", "beta"]);
+        let mut prefs = AccountPrefs {
+            channel_order: names(&["beta", "alpha"]),
+            favorite_channels: names(&["alpha"]),
+            ..Default::default()
+        };
+        prefs
+            .channel_folders
+            .insert("alpha".to_string(), "Work".to_string());
+        let before = prefs.clone();
+
+        prefs.move_channel("alpha", "missing", &channels);
+        prefs.move_channel("alpha", "alpha", &channels);
+        assert!(prefs == before);
+    }
+
+    #[test]
+    fn move_channel_adopts_target_folder_and_favorite() {
+        let channels = names(&["alpha", "beta", "gamma"]);
+        let mut prefs = AccountPrefs::default();
+        prefs
+            .channel_folders
+            .insert("beta".to_string(), "Work".to_string());
+        prefs.favorite_channels.push("beta".to_string());
+
+        prefs.move_channel("gamma", "beta", &channels);
+        assert_eq!(
+            prefs.channel_folders.get("gamma").map(String::as_str),
+            Some("Work")
+        );
+        assert!(prefs.is_favorite("gamma"));
+
+        prefs.move_channel("gamma", "alpha", &channels);
+        assert!(!prefs.channel_folders.contains_key("gamma"));
+        assert!(!prefs.is_favorite("gamma"));
+    }
+
+    #[test]
+    fn prefs_with_local_topics_still_load() {
+        let prefs: AccountPrefs = serde_json::from_str(
+            r#"{"muted_channels":["a"],"channel_topics":{"a":"old local note"}}"#,
+        )
+        .unwrap();
+        assert_eq!(prefs.muted_channels, names(&["a"]));
+    }
+
+    fn account(url: &str, token: &str) -> Account {
+        let field = |name: &str, value| AuthField {
+            name: name.to_string(),
+            display: None,
+            value,
+            required: true,
+        };
+        Account {
+            auth: vec![
+                field("sockchat_url", FieldValue::Text(Some(url.to_string()))),
+                field("token", FieldValue::Password(Some(token.to_string()))),
+            ],
+            protocol_name: "sockchat".to_string(),
+            private_profile: None,
+            autoconnect: false,
+        }
+    }
+
+    #[test]
+    fn account_key_leaves_out_passwords() {
+        let key = account_key(&account("wss://chat.example", "secret"));
+        assert!(key.contains("wss://chat.example"));
+        assert!(!key.contains("secret"));
+        assert_eq!(key, account_key(&account("wss://chat.example", "rotated")));
+    }
+
+    #[test]
+    fn restored_popouts_follow_their_account_after_a_delete() {
+        let mut accounts = vec![
+            account("wss://a.example", ""),
+            account("wss://b.example", ""),
+        ];
+        let session = SessionState {
+            popouts: vec![PopoutWindow::new(
+                account_key(&accounts[1]),
+                "lobby".to_string(),
+            )],
+            ..Default::default()
+        };
+        let restored: SessionState =
+            serde_json::from_str(&serde_json::to_string(&session).unwrap()).unwrap();
+        assert!(restored == session);
+
+        let popout = &restored.popouts[0];
+        assert_eq!(find_account(&accounts, &popout.account), Some(1));
+        accounts.remove(0);
+        assert_eq!(find_account(&accounts, &popout.account), Some(0));
+        accounts.remove(0);
+        assert_eq!(find_account(&accounts, &popout.account), None);
+    }
+
+    #[test]
+    fn session_round_trips_and_refers_to_accounts_by_key() {
+        let mut accounts = vec![
+            account("wss://a.example", ""),
+            account("wss://b.example", ""),
+        ];
+        let key = account_key(&accounts[1]);
+        let mut session = SessionState {
+            panes: vec![ChannelPane {
+                account: key.clone(),
+                channel_id: "lobby".to_string(),
+            }],
+            split: SplitDirection::Vertical,
+            window_size: Some([800.0, 600.0]),
+            active_account: Some(key.clone()),
+            active_channel: Some("lobby".to_string()),
+            ..Default::default()
+        };
+        session.panel_widths.insert("users".to_string(), 300.0);
+
+        let restored: SessionState =
+            serde_json::from_str(&serde_json::to_string(&session).unwrap()).unwrap();
+        assert!(restored == session);
+        assert_eq!(restored.panel_width("users", 360.0), 300.0);
+        assert_eq!(restored.panel_width("channels", 220.0), 220.0);
+
+        accounts.remove(0);
+        assert_eq!(find_account(&accounts, &restored.panes[0].account), Some(0));
+        assert_eq!(
+            restored
+                .active_account
+                .and_then(|key| find_account(&accounts, &key)),
+            Some(0)
+        );
+    }
+}
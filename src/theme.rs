@@ -0,0 +1,274 @@
+use crate::utils::config_path;
+use eframe::egui::{self, Color32, Visuals};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+mod hex_color {
+    use eframe::egui::Color32;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color32, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        if a == 255 {
+            serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b))
+        } else {
+            serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let hex = text.trim().trim_start_matches('#');
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid color {:?}", text)))
+        };
+        match hex.len() {
+            6 => Ok(Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?)),
+            8 => Ok(Color32::from_rgba_unmultiplied(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+                channel(6)?,
+            )),
+            _ => Err(serde::de::Error::custom(format!(
+                "invalid color {:?}",
+                text
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChatColors {
+    #[serde(with = "hex_color")]
+    pub own_message: Color32,
+    #[serde(with = "hex_color")]
+    pub normal: Color32,
+    #[serde(with = "hex_color")]
+    pub server: Color32,
+    #[serde(with = "hex_color")]
+    pub meta: Color32,
+    #[serde(with = "hex_color")]
+    pub mention: Color32,
+    #[serde(with = "hex_color")]
+    pub link: Color32,
+    #[serde(with = "hex_color")]
+    pub command: Color32,
+    #[serde(with = "hex_color")]
+    pub asset: Color32,
+    #[serde(with = "hex_color")]
+    pub highlight: Color32,
+    #[serde(with = "hex_color")]
+    pub online: Color32,
+    #[serde(with = "hex_color")]
+    pub away: Color32,
+    #[serde(with = "hex_color")]
+    pub offline: Color32,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub dark: bool,
+    #[serde(with = "hex_color")]
+    pub background: Color32,
+    #[serde(with = "hex_color")]
+    pub window: Color32,
+    #[serde(with = "hex_color")]
+    pub text: Color32,
+    #[serde(with = "hex_color")]
+    pub accent: Color32,
+    pub chat: ChatColors,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        let visuals = Visuals::dark();
+        Theme {
+            name: "Dark".to_string(),
+            dark: true,
+            background: visuals.panel_fill,
+            window: visuals.window_fill,
+            text: visuals.widgets.noninteractive.fg_stroke.color,
+            accent: visuals.selection.bg_fill,
+            chat: ChatColors {
+                own_message: Color32::GREEN,
+                normal: Color32::WHITE,
+                server: Color32::YELLOW,
+                meta: Color32::GRAY,
+                mention: Color32::LIGHT_RED,
+                link: visuals.hyperlink_color,
+                command: Color32::from_rgb(100, 150, 255),
+                asset: Color32::YELLOW,
+                highlight: Color32::from_rgba_unmultiplied(255, 255, 255, 12),
+                online: Color32::from_rgb(80, 200, 120),
+                away: Color32::from_rgb(230, 180, 60),
+                offline: Color32::GRAY,
+            },
+        }
+    }
+
+    pub fn light() -> Self {
+        let visuals = Visuals::light();
+        Theme {
+            name: "Light".to_string(),
+            dark: false,
+            background: visuals.panel_fill,
+            window: visuals.window_fill,
+            text: visuals.widgets.noninteractive.fg_stroke.color,
+            accent: visuals.selection.bg_fill,
+            chat: ChatColors {
+                own_message: Color32::from_rgb(0, 128, 0),
+                normal: Color32::from_gray(20),
+                server: Color32::from_rgb(160, 110, 0),
+                meta: Color32::GRAY,
+                mention: Color32::from_rgb(200, 30, 30),
+                link: visuals.hyperlink_color,
+                command: Color32::from_rgb(40, 80, 200),
+                asset: Color32::from_rgb(160, 110, 0),
+                highlight: Color32::from_rgba_unmultiplied(0, 0, 0, 12),
+                online: Color32::from_rgb(30, 150, 70),
+                away: Color32::from_rgb(190, 130, 0),
+                offline: Color32::GRAY,
+            },
+        }
+    }
+
+    pub fn visuals(&self) -> Visuals {
+        let mut visuals = if self.dark {
+            Visuals::dark()
+        } else {
+            Visuals::light()
+        };
+        visuals.panel_fill = self.background;
+        visuals.window_fill = self.window;
+        visuals.widgets.noninteractive.fg_stroke.color = self.text;
+        visuals.selection.bg_fill = self.accent;
+        visuals.hyperlink_color = self.chat.link;
+        visuals
+    }
+
+    pub fn apply(&self, ctx: &egui::Context) {
+        ctx.set_visuals(self.visuals());
+        ctx.data_mut(|d| d.insert_temp(egui::Id::new("chat_colors"), self.chat));
+    }
+}
+
+pub fn colors(ctx: &egui::Context) -> ChatColors {
+    ctx.data(|d| d.get_temp(egui::Id::new("chat_colors")))
+        .unwrap_or(Theme::dark().chat)
+}
+
+pub fn themes_dir() -> PathBuf {
+    let path = config_path("themes");
+    std::fs::create_dir_all(&path).ok();
+    path
+}
+
+fn merge(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn load_theme(path: &std::path::Path) -> Option<Theme> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let overlay: serde_json::Value = serde_json::from_str(&content).ok()?;
+    let base = if overlay.get("dark") == Some(&serde_json::Value::Bool(false)) {
+        Theme::light()
+    } else {
+        Theme::dark()
+    };
+
+    let mut value = serde_json::to_value(base).ok()?;
+    merge(&mut value, overlay);
+    let mut theme: Theme = serde_json::from_value(value).ok()?;
+    if theme.name == "Dark" || theme.name == "Light" {
+        theme.name = path.file_stem()?.to_string_lossy().to_string();
+    }
+    Some(theme)
+}
+
+pub struct ThemeSet {
+    custom: Vec<Theme>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_scan: Option<Instant>,
+}
+
+impl ThemeSet {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet {
+            custom: Vec::new(),
+            modified: HashMap::new(),
+            last_scan: None,
+        };
+        themes.poll();
+        themes
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![Theme::dark().name, Theme::light().name];
+        names.extend(self.custom.iter().map(|t| t.name.clone()));
+        names
+    }
+
+    pub fn get(&self, name: &str) -> Theme {
+        self.custom
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+            .unwrap_or_else(|| {
+                if name == "Light" {
+                    Theme::light()
+                } else {
+                    Theme::dark()
+                }
+            })
+    }
+
+    pub fn poll(&mut self) {
+        if self
+            .last_scan
+            .is_some_and(|last| last.elapsed() < RESCAN_INTERVAL)
+        {
+            return;
+        }
+        self.last_scan = Some(Instant::now());
+
+        let modified: HashMap<PathBuf, SystemTime> = std::fs::read_dir(themes_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .filter_map(|path| {
+                let time = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, time))
+            })
+            .collect();
+        if modified == self.modified {
+            return;
+        }
+
+        let mut paths: Vec<&PathBuf> = modified.keys().collect();
+        paths.sort();
+        self.custom = paths.into_iter().filter_map(|p| load_theme(p)).collect();
+        self.modified = modified;
+    }
+}
@@ -1,19 +1,170 @@
-use eframe::egui::Color32;
-use oshatori::Account;
+use crate::state::{AccountPrefs, Capabilities, ChatSettings, Presence, SessionState, TimeFormat};
+use chrono::{DateTime, Local, Utc};
+use eframe::egui::{ecolor::Hsva, Color32, Rgba};
+use oshatori::{Account, Asset, Message, MessageFragment, Profile};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 pub fn color32(color: [u8; 4]) -> Color32 {
     Color32::from_rgba_unmultiplied(color[0], color[1], color[2], color[3])
 }
 
-pub fn accounts_path() -> PathBuf {
+pub fn stable_color(user_id: &str) -> [u8; 4] {
+    let hash = user_id.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    let hue = (hash % 360) as f32 / 360.0;
+    Color32::from(Hsva::new(hue, 0.55, 0.9, 1.0)).to_array()
+}
+
+pub fn user_color(
+    user_id: &str,
+    profile: Option<&Profile>,
+    prefs: &AccountPrefs,
+    settings: &ChatSettings,
+    background: Color32,
+) -> Option<Color32> {
+    prefs
+        .colors
+        .get(user_id)
+        .copied()
+        .or_else(|| profile.and_then(|p| p.color))
+        .or_else(|| settings.auto_user_colors.then(|| stable_color(user_id)))
+        .map(|color| {
+            if settings.readable_colors {
+                readable_color(color32(color), background)
+            } else {
+                color32(color)
+            }
+        })
+}
+
+pub const MIN_CONTRAST: f32 = 4.5;
+
+fn relative_luminance(color: Color32) -> f32 {
+    let [r, g, b, _] = Rgba::from(color).to_array();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+pub fn contrast_ratio(a: Color32, b: Color32) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+fn to_hsl(color: Color32) -> (f32, f32, f32) {
+    let [r, g, b] = [color.r(), color.g(), color.b()].map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+fn from_hsl(h: f32, s: f32, l: f32, alpha: u8) -> Color32 {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h * 6.0;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let [r, g, b] = [r, g, b].map(|v| ((v + m).clamp(0.0, 1.0) * 255.0).round() as u8);
+    Color32::from_rgba_unmultiplied(r, g, b, alpha)
+}
+
+pub fn readable_color(color: Color32, background: Color32) -> Color32 {
+    if contrast_ratio(color, background) >= MIN_CONTRAST {
+        return color;
+    }
+
+    let (h, s, l) = to_hsl(color);
+    let lighten = relative_luminance(background) < 0.18;
+    let (mut lo, mut hi) = if lighten { (l, 1.0) } else { (0.0, l) };
+    for _ in 0..16 {
+        let mid = (lo + hi) / 2.0;
+        let passes = contrast_ratio(from_hsl(h, s, mid, color.a()), background) >= MIN_CONTRAST;
+        if passes == lighten {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    from_hsl(h, s, if lighten { hi } else { lo }, color.a())
+}
+
+// Sockchat has no presence state; `/afk reason` renames the user to
+// `<REASON>_name` (HTML-escaped on the wire), so that prefix is the only hint
+// that someone is away. Other protocols report no presence at all and can
+// legitimately have names shaped like this, so they get `None`.
+pub fn presence(profile: &Profile, capabilities: Capabilities) -> Option<Presence> {
+    if !capabilities.away_name_prefix {
+        return None;
+    }
+    let away = [profile.username.as_deref(), profile.display_name.as_deref()]
+        .into_iter()
+        .flatten()
+        .any(|name| {
+            let name = name.replace("&lt;", "<").replace("&gt;", ">");
+            name.starts_with('<') && name.contains(">_")
+        });
+    Some(if away {
+        Presence::Away
+    } else {
+        Presence::Online
+    })
+}
+
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+
+    for qc in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = pos + text[pos..].iter().position(|c| *c == qc)?;
+        score += match last {
+            Some(l) if found == l + 1 => 5,
+            _ => 1,
+        };
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(found);
+        pos = found + 1;
+    }
+
+    Some(score * 10 - text.len() as i32)
+}
+
+pub fn config_path(file: &str) -> PathBuf {
     let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
     path.push("taitsu");
     std::fs::create_dir_all(&path).ok();
-    path.push("accounts.json");
+    path.push(file);
     path
 }
 
+pub fn accounts_path() -> PathBuf {
+    config_path("accounts.json")
+}
+
 pub fn load_accounts() -> Vec<Account> {
     std::fs::read_to_string(accounts_path())
         .ok()
@@ -26,3 +177,313 @@ pub fn save_accounts(accounts: &[Account]) {
         std::fs::write(accounts_path(), content).ok();
     }
 }
+
+pub fn load_account_prefs(count: usize) -> Vec<AccountPrefs> {
+    let mut prefs: Vec<AccountPrefs> = std::fs::read_to_string(config_path("account_prefs.json"))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default();
+    prefs.resize(count, AccountPrefs::default());
+    prefs
+}
+
+pub fn save_account_prefs(prefs: &[AccountPrefs]) {
+    if let Ok(content) = serde_json::to_string_pretty(prefs) {
+        std::fs::write(config_path("account_prefs.json"), content).ok();
+    }
+}
+
+pub fn load_settings() -> ChatSettings {
+    std::fs::read_to_string(config_path("settings.json"))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &ChatSettings) {
+    if let Ok(content) = serde_json::to_string_pretty(settings) {
+        std::fs::write(config_path("settings.json"), content).ok();
+    }
+}
+
+pub fn load_session() -> SessionState {
+    std::fs::read_to_string(config_path("session.json"))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_session(session: &SessionState) {
+    if let Ok(content) = serde_json::to_string_pretty(session) {
+        std::fs::write(config_path("session.json"), content).ok();
+    }
+}
+
+pub fn media_cache_dir() -> PathBuf {
+    let mut path = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("taitsu");
+    path.push("media");
+    std::fs::create_dir_all(&path).ok();
+    path
+}
+
+pub fn message_text(content: &[MessageFragment], assets: &HashMap<String, Asset>) -> String {
+    content
+        .iter()
+        .map(|fragment| match fragment {
+            MessageFragment::Text(text) => text.clone(),
+            MessageFragment::Url(url) => url.clone(),
+            MessageFragment::Image { url, .. }
+            | MessageFragment::Video { url, .. }
+            | MessageFragment::Audio { url, .. } => url.clone(),
+            MessageFragment::AssetId(id) => match assets.get(id) {
+                Some(
+                    Asset::Emote { pattern, .. }
+                    | Asset::Sticker { pattern, .. }
+                    | Asset::Audio { pattern, .. }
+                    | Asset::Command { pattern, .. },
+                ) => pattern.clone(),
+                None => id.clone(),
+            },
+        })
+        .collect()
+}
+
+pub fn message_key(msg: &Message) -> String {
+    msg.id.clone().unwrap_or_else(|| {
+        format!(
+            "{}@{}",
+            msg.sender_id.as_deref().unwrap_or(""),
+            msg.timestamp.timestamp_millis()
+        )
+    })
+}
+
+pub fn format_timestamp(timestamp: DateTime<Utc>, settings: &ChatSettings) -> String {
+    let local = timestamp.with_timezone(&Local);
+    match (settings.time_format, settings.show_seconds) {
+        (TimeFormat::H24, true) => local.format("%H:%M:%S").to_string(),
+        (TimeFormat::H24, false) => local.format("%H:%M").to_string(),
+        (TimeFormat::H12, true) => local.format("%I:%M:%S %p").to_string(),
+        (TimeFormat::H12, false) => local.format("%I:%M %p").to_string(),
+        (TimeFormat::Relative, _) => {
+            let elapsed = Utc::now().signed_duration_since(timestamp);
+            if elapsed.num_seconds() < 60 {
+                "just now".to_string()
+            } else if elapsed.num_minutes() < 60 {
+                format!("{}m ago", elapsed.num_minutes())
+            } else if elapsed.num_hours() < 24 {
+                format!("{}h ago", elapsed.num_hours())
+            } else if elapsed.num_days() < 7 {
+                format!("{}d ago", elapsed.num_days())
+            } else {
+                local.format("%Y-%m-%d").to_string()
+            }
+        }
+    }
+}
+
+pub fn full_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp
+        .with_timezone(&Local)
+        .format("%A, %B %-d, %Y %H:%M:%S %:z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_color_is_deterministic_and_opaque() {
+        assert_eq!(stable_color("42"), stable_color("42"));
+        for id in ["1", "42", "alice", ""] {
+            assert_eq!(stable_color(id)[3], 255);
+        }
+    }
+
+    #[test]
+    fn stable_color_spreads_ids_across_hues() {
+        let colors: std::collections::HashSet<[u8; 4]> =
+            (0..50).map(|i| stable_color(&i.to_string())).collect();
+        assert!(colors.len() > 40);
+    }
+
+    #[test]
+    fn stable_color_is_bright_enough_to_read() {
+        for i in 0..50 {
+            let [r, g, b, _] = stable_color(&i.to_string());
+            assert!(r.max(g).max(b) >= 220);
+        }
+    }
+
+    #[test]
+    fn contrast_ratio_matches_wcag_extremes() {
+        assert!((contrast_ratio(Color32::BLACK, Color32::WHITE) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(Color32::GRAY, Color32::GRAY) - 1.0).abs() < f32::EPSILON);
+        let (a, b) = (
+            Color32::from_rgb(200, 40, 40),
+            Color32::from_rgb(30, 30, 30),
+        );
+        assert_eq!(contrast_ratio(a, b), contrast_ratio(b, a));
+    }
+
+    #[test]
+    fn readable_color_keeps_colors_that_already_pass() {
+        let color = Color32::from_rgb(240, 200, 60);
+        let background = Color32::from_rgb(27, 27, 27);
+        assert!(contrast_ratio(color, background) >= MIN_CONTRAST);
+        assert_eq!(readable_color(color, background), color);
+    }
+
+    #[test]
+    fn readable_color_lightens_on_dark_and_darkens_on_light() {
+        let dark = Color32::from_rgb(27, 27, 27);
+        let light = Color32::from_rgb(248, 248, 248);
+
+        let navy = Color32::from_rgb(20, 30, 140);
+        let lightened = readable_color(navy, dark);
+        assert!(contrast_ratio(lightened, dark) >= MIN_CONTRAST);
+        assert!(relative_luminance(lightened) > relative_luminance(navy));
+
+        let yellow = Color32::from_rgb(240, 200, 60);
+        let darkened = readable_color(yellow, light);
+        assert!(contrast_ratio(darkened, light) >= MIN_CONTRAST);
+        assert!(relative_luminance(darkened) < relative_luminance(yellow));
+    }
+
+    #[test]
+    fn readable_color_preserves_hue_and_alpha() {
+        let color = Color32::from_rgba_unmultiplied(120, 40, 160, 200);
+        let adjusted = readable_color(color, Color32::from_rgb(27, 27, 27));
+        assert_eq!(adjusted.a(), 200);
+        let (h, _, _) = to_hsl(color);
+        let (adjusted_h, _, _) =
+            to_hsl(Color32::from_rgb(adjusted.r(), adjusted.g(), adjusted.b()));
+        assert!((h - adjusted_h).abs() < 0.02);
+    }
+
+    fn message(id: Option<&str>, content: Vec<MessageFragment>) -> Message {
+        Message {
+            id: id.map(str::to_string),
+            sender_id: Some("7".to_string()),
+            content,
+            timestamp: DateTime::from_timestamp_millis(1_700_000_000_123).unwrap(),
+            message_type: oshatori::MessageType::Normal,
+            status: oshatori::MessageStatus::Sent,
+        }
+    }
+
+    #[test]
+    fn message_text_flattens_fragments_and_assets() {
+        let assets = HashMap::from([(
+            "wave".to_string(),
+            Asset::Emote {
+                id: None,
+                pattern: ":wave:".to_string(),
+                src: String::new(),
+                source: oshatori::AssetSource::Server,
+            },
+        )]);
+        let content = vec![
+            MessageFragment::Text("hi ".to_string()),
+            MessageFragment::AssetId("wave".to_string()),
+            MessageFragment::Text(" see ".to_string()),
+            MessageFragment::Url("https://example.com".to_string()),
+            MessageFragment::AssetId("missing".to_string()),
+        ];
+        assert_eq!(
+            message_text(&content, &assets),
+            "hi :wave: see https://example.commissing"
+        );
+    }
+
+    #[test]
+    fn message_key_falls_back_to_sender_and_time() {
+        assert_eq!(message_key(&message(Some("42"), vec![])), "42");
+        assert_eq!(message_key(&message(None, vec![])), "7@1700000000123");
+    }
+
+    fn timestamp_with(time_format: TimeFormat, show_seconds: bool, at: DateTime<Utc>) -> String {
+        let settings = ChatSettings {
+            time_format,
+            show_seconds,
+            ..ChatSettings::default()
+        };
+        format_timestamp(at, &settings)
+    }
+
+    #[test]
+    fn format_timestamp_uses_local_clock_formats() {
+        use chrono::TimeZone;
+        let at = Local
+            .with_ymd_and_hms(2024, 3, 9, 13, 5, 9)
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(timestamp_with(TimeFormat::H24, true, at), "13:05:09");
+        assert_eq!(timestamp_with(TimeFormat::H24, false, at), "13:05");
+        assert_eq!(timestamp_with(TimeFormat::H12, true, at), "01:05:09 PM");
+        assert_eq!(timestamp_with(TimeFormat::H12, false, at), "01:05 PM");
+        assert_eq!(timestamp_with(TimeFormat::Relative, true, at), "2024-03-09");
+    }
+
+    #[test]
+    fn format_timestamp_relative_steps() {
+        let ago = |secs| {
+            timestamp_with(
+                TimeFormat::Relative,
+                true,
+                Utc::now() - chrono::Duration::seconds(secs),
+            )
+        };
+        assert_eq!(ago(10), "just now");
+        assert_eq!(ago(5 * 60), "5m ago");
+        assert_eq!(ago(3 * 60 * 60), "3h ago");
+        assert_eq!(ago(2 * 24 * 60 * 60), "2d ago");
+    }
+
+    fn named(username: &str) -> Profile {
+        Profile {
+            id: Some("1".to_string()),
+            username: Some(username.to_string()),
+            display_name: None,
+            color: None,
+            picture: None,
+        }
+    }
+
+    #[test]
+    fn presence_detects_sockchat_afk_prefix() {
+        let sockchat = Capabilities::for_protocol("sockchat");
+        let presence = |name| presence(&named(name), sockchat);
+        assert!(presence("<AFK>_alice") == Some(Presence::Away));
+        assert!(presence("&lt;BRB&gt;_alice") == Some(Presence::Away));
+        assert!(presence("alice") == Some(Presence::Online));
+        assert!(presence("<alice>") == Some(Presence::Online));
+    }
+
+    #[test]
+    fn presence_is_unknown_without_away_prefixes() {
+        let mock = Capabilities::for_protocol("Mock");
+        assert!(presence(&named("<AFK>_alice"), mock).is_none());
+        assert!(presence(&named("alice"), mock).is_none());
+    }
+
+    #[test]
+    fn fuzzy_score_requires_characters_in_order() {
+        assert!(fuzzy_score("gnrl", "general").is_some());
+        assert!(fuzzy_score("GEN", "general").is_some());
+        assert!(fuzzy_score("lrng", "general").is_none());
+        assert!(fuzzy_score("generals", "general").is_none());
+        assert!(fuzzy_score("off topic", "offtopic").is_some());
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_contiguous_and_word_start_matches() {
+        let score = |query, text| fuzzy_score(query, text).unwrap();
+        assert!(score("gen", "general") > score("gen", "gardening"));
+        assert!(score("dev", "#dev-chat") > score("dev", "#undevised"));
+        // Shorter names win when the match is otherwise the same.
+        assert!(score("dev", "dev") > score("dev", "developers"));
+    }
+}